@@ -0,0 +1,48 @@
+use druid::{
+    widget::{Flex, LineBreaking},
+    AppLauncher, Color, Data, Lens, Widget, WidgetExt, WindowDesc,
+};
+use druid_selectable_label::SelectableLabel;
+
+#[derive(Clone, Data, Lens)]
+struct State {
+    title: String,
+    body: String,
+}
+
+fn build_ui() -> impl Widget<State> {
+    Flex::column()
+        .with_child(
+            SelectableLabel::new()
+                .with_text_size(24.0)
+                .lens(State::title),
+        )
+        .with_spacer(16.0)
+        .with_flex_child(
+            SelectableLabel::new()
+                .with_line_break_mode(LineBreaking::WordWrap)
+                .with_selection_color(Color::rgba8(0xff, 0xc1, 0x07, 0x80))
+                .lens(State::body)
+                .expand_width(),
+            1.0,
+        )
+        .padding(20.0)
+}
+
+fn main() {
+    let window = WindowDesc::new(build_ui())
+        .title("Selectable label")
+        .window_size((480.0, 320.0));
+
+    let state = State {
+        title: "Select me".to_string(),
+        body: "Drag to select some text, double-click to select a word and triple-click to select a line.\n\
+               Press Ctrl+C to copy the selection and Ctrl+A to select everything."
+            .to_string(),
+    };
+
+    AppLauncher::with_window(window)
+        .log_to_console()
+        .launch(state)
+        .expect("Failed to launch application");
+}
@@ -0,0 +1,5 @@
+//! A read-only druid label whose text can be selected with the mouse and copied.
+
+pub mod selectable_label;
+
+pub use selectable_label::SelectableLabel;
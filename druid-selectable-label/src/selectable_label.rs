@@ -1 +1,366 @@
-pub mod selectable_label;
+use std::ops::Range;
+
+use druid::{
+    commands,
+    kurbo::Insets,
+    piet::TextLayout as _,
+    text::{Selection, TextLayout, TextStorage},
+    theme,
+    widget::LineBreaking,
+    Application, BoxConstraints, Color, Cursor, Env, Event, EventCtx, FontDescriptor, KeyOrValue, LayoutCtx,
+    LifeCycle, LifeCycleCtx, MouseButton, PaintCtx, Point, RenderContext, Size, UpdateCtx, Widget,
+};
+
+/// What a mouse drag extends the selection by
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Granularity {
+    Character,
+    Word,
+    Line,
+}
+
+/// A read-only text that can be selected with the mouse and copied.
+///
+/// - click and drag to select characters, shift+click to extend the selection
+/// - double-click to select a word, triple-click to select a line
+/// - Ctrl+C (Cmd+C on macOS) or the `COPY` command copies the selection
+/// - Ctrl+A selects everything, Escape clears the selection
+///
+/// The highlight uses [`theme::SELECTED_TEXT_BACKGROUND_COLOR`] unless another
+/// color is set with [`with_selection_color`](SelectableLabel::with_selection_color).
+pub struct SelectableLabel<T> {
+    layout: TextLayout<T>,
+    line_break_mode: LineBreaking,
+    selection_color: KeyOrValue<Color>,
+    selection: Selection,
+    granularity: Granularity,
+    /// Range selected by the mouse down that started the current drag
+    drag_origin: Range<usize>,
+}
+
+impl<T: TextStorage> SelectableLabel<T> {
+    pub fn new() -> Self {
+        Self {
+            layout: TextLayout::new(),
+            line_break_mode: LineBreaking::Overflow,
+            selection_color: theme::SELECTED_TEXT_BACKGROUND_COLOR.into(),
+            selection: Selection::caret(0),
+            granularity: Granularity::Character,
+            drag_origin: 0..0,
+        }
+    }
+
+    pub fn with_text_color(mut self, color: impl Into<KeyOrValue<Color>>) -> Self {
+        self.layout.set_text_color(color);
+        self
+    }
+
+    pub fn with_font(mut self, font: impl Into<KeyOrValue<FontDescriptor>>) -> Self {
+        self.layout.set_font(font);
+        self
+    }
+
+    pub fn with_text_size(mut self, size: impl Into<KeyOrValue<f64>>) -> Self {
+        self.layout.set_text_size(size);
+        self
+    }
+
+    pub fn with_line_break_mode(mut self, mode: LineBreaking) -> Self {
+        self.line_break_mode = mode;
+        self
+    }
+
+    /// Color painted behind the selected text
+    pub fn with_selection_color(mut self, color: impl Into<KeyOrValue<Color>>) -> Self {
+        self.selection_color = color.into();
+        self
+    }
+
+    /// The current selection, as byte offsets in the text
+    pub fn selection(&self) -> Selection {
+        self.selection
+    }
+
+    /// The selected text, if the selection is not empty
+    pub fn selected_text(&self) -> Option<String> {
+        if self.selection.is_caret() {
+            return None;
+        }
+        self.text().get(self.selection.range()).map(str::to_string)
+    }
+
+    fn text(&self) -> &str {
+        self.layout.text().map(|text| text.as_str()).unwrap_or_default()
+    }
+
+    fn set_selection(&mut self, ctx: &mut EventCtx, selection: Selection) {
+        let selection = selection.constrained(self.text());
+        if selection != self.selection {
+            self.selection = selection;
+            ctx.request_paint();
+        }
+    }
+
+    /// Put the selected text in the clipboard, the event is left unhandled if nothing is selected
+    fn copy_selection(&self, ctx: &mut EventCtx) {
+        if let Some(text) = self.selected_text() {
+            Application::global().clipboard().put_string(text);
+            ctx.set_handled();
+        }
+    }
+
+    /// Range selected around `offset` with the given granularity
+    fn range_at(&self, offset: usize, granularity: Granularity) -> Range<usize> {
+        match granularity {
+            Granularity::Character => offset..offset,
+            Granularity::Word => word_range(self.text(), offset),
+            Granularity::Line => self.line_range(offset),
+        }
+    }
+
+    /// Range of the visual line containing `offset`, without its line break
+    fn line_range(&self, offset: usize) -> Range<usize> {
+        let Some(layout) = self.layout.layout() else {
+            return 0..self.text().len();
+        };
+        let line = layout.hit_test_text_position(offset).line;
+        match layout.line_metric(line) {
+            Some(metric) => {
+                let line_text = &self.text()[metric.start_offset..metric.end_offset];
+                metric.start_offset..metric.start_offset + line_text.trim_end_matches(['\r', '\n']).len()
+            },
+            None => 0..self.text().len(),
+        }
+    }
+
+    /// Select from the drag origin to the range under `offset`
+    fn extend_drag(&mut self, ctx: &mut EventCtx, offset: usize) {
+        let range = self.range_at(offset, self.granularity);
+        let selection = if range.start < self.drag_origin.start {
+            Selection::new(self.drag_origin.end, range.start)
+        } else {
+            Selection::new(self.drag_origin.start, range.end.max(self.drag_origin.end))
+        };
+        self.set_selection(ctx, selection);
+    }
+
+    fn offset_for_point(&self, point: Point) -> usize {
+        self.layout.text_position_for_point(point)
+    }
+}
+
+impl<T: TextStorage> Default for SelectableLabel<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: TextStorage> Widget<T> for SelectableLabel<T> {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, _data: &mut T, _env: &Env) {
+        match event {
+            Event::MouseDown(mouse) if mouse.button == MouseButton::Left => {
+                ctx.request_focus();
+                ctx.set_active(true);
+                let offset = self.offset_for_point(mouse.pos);
+                self.granularity = match mouse.count {
+                    0 | 1 => Granularity::Character,
+                    2 => Granularity::Word,
+                    _ => Granularity::Line,
+                };
+                if mouse.mods.shift() && self.granularity == Granularity::Character {
+                    self.drag_origin = self.selection.anchor..self.selection.anchor;
+                    self.extend_drag(ctx, offset);
+                } else {
+                    self.drag_origin = self.range_at(offset, self.granularity);
+                    self.set_selection(ctx, Selection::new(self.drag_origin.start, self.drag_origin.end));
+                }
+                ctx.set_handled();
+            },
+            Event::MouseMove(mouse) => {
+                ctx.set_cursor(&Cursor::IBeam);
+                if ctx.is_active() {
+                    let offset = self.offset_for_point(mouse.pos);
+                    self.extend_drag(ctx, offset);
+                    ctx.set_handled();
+                }
+            },
+            Event::MouseUp(mouse) if mouse.button == MouseButton::Left && ctx.is_active() => {
+                ctx.set_active(false);
+                ctx.set_handled();
+            },
+            Event::KeyDown(key) if ctx.is_focused() => {
+                let shortcut = key.mods.ctrl() || key.mods.meta();
+                match &key.key {
+                    druid::keyboard_types::Key::Character(c) if shortcut && c.eq_ignore_ascii_case("c") => {
+                        self.copy_selection(ctx);
+                    },
+                    druid::keyboard_types::Key::Character(c) if shortcut && c.eq_ignore_ascii_case("a") => {
+                        let len = self.text().len();
+                        self.set_selection(ctx, Selection::new(0, len));
+                        ctx.set_handled();
+                    },
+                    druid::keyboard_types::Key::Escape if !self.selection.is_caret() => {
+                        let active = self.selection.active;
+                        self.set_selection(ctx, Selection::caret(active));
+                        ctx.set_handled();
+                    },
+                    _ => {},
+                }
+            },
+            Event::Command(cmd) if cmd.is(commands::COPY) && ctx.is_focused() => {
+                self.copy_selection(ctx);
+            },
+            _ => {},
+        }
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &T, _env: &Env) {
+        match event {
+            LifeCycle::WidgetAdded => self.layout.set_text(data.clone()),
+            // Only the focused label shows a selection
+            LifeCycle::FocusChanged(false) => {
+                self.selection = Selection::caret(0);
+                ctx.request_paint();
+            },
+            _ => {},
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &T, data: &T, _env: &Env) {
+        if !old_data.same(data) {
+            self.layout.set_text(data.clone());
+            self.selection = self.selection.constrained(data.as_str());
+            ctx.request_layout();
+        }
+        if self.layout.needs_rebuild_after_update(ctx) {
+            ctx.request_layout();
+        }
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &T, env: &Env) -> Size {
+        let width = match self.line_break_mode {
+            LineBreaking::WordWrap => bc.max().width,
+            _ => f64::INFINITY,
+        };
+        self.layout.set_wrap_width(width);
+        self.layout.rebuild_if_needed(ctx.text(), env);
+
+        let text_metrics = self.layout.layout_metrics();
+        ctx.set_baseline_offset(text_metrics.size.height - text_metrics.first_baseline);
+        let size = bc.constrain(text_metrics.size);
+        if self.line_break_mode == LineBreaking::Overflow {
+            let overflow = (text_metrics.size.width - size.width).max(0.0);
+            ctx.set_paint_insets(Insets::new(0.0, 0.0, overflow, 0.0));
+        }
+        size
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, _data: &T, env: &Env) {
+        let clip = self.line_break_mode == LineBreaking::Clip;
+        ctx.with_save(|ctx| {
+            if clip {
+                let clip_rect = ctx.size().to_rect();
+                ctx.clip(clip_rect);
+            }
+            if !self.selection.is_caret() {
+                let color = self.selection_color.resolve(env);
+                for rect in self.layout.rects_for_range(self.selection.range()) {
+                    ctx.fill(rect, &color);
+                }
+            }
+            self.layout.draw(ctx, Point::ORIGIN);
+        });
+    }
+}
+
+/// Range of the word around `offset`, or of the single character if it is not part of a word
+fn word_range(text: &str, offset: usize) -> Range<usize> {
+    let offset = offset.min(text.len());
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let start = text[..offset]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word(*c))
+        .last()
+        .map(|(i, _)| i)
+        .unwrap_or(offset);
+    let end = text[offset..]
+        .char_indices()
+        .find(|(_, c)| !is_word(*c))
+        .map(|(i, _)| offset + i)
+        .unwrap_or(text.len());
+    if start == end {
+        // Not in a word: select the character under the mouse
+        let end = text[offset..].chars().next().map(|c| offset + c.len_utf8()).unwrap_or(offset);
+        return offset..end;
+    }
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use druid::{piet::Device, FontDescriptor, FontFamily};
+
+    use super::*;
+
+    /// A label laid out with a concrete font, as the widget is after its first layout
+    fn label(text: &str) -> SelectableLabel<String> {
+        let mut label = SelectableLabel::new()
+            .with_font(FontDescriptor::new(FontFamily::MONOSPACE))
+            .with_text_color(Color::BLACK)
+            .with_text_size(12.0);
+        label.layout.set_text(text.to_string());
+        let mut device = Device::new().unwrap();
+        let mut target = device.bitmap_target(100, 100, 1.0).unwrap();
+        let mut text = target.render_context().text().clone();
+        label.layout.rebuild_if_needed(&mut text, &Env::empty());
+        label
+    }
+
+    #[test]
+    fn selects_the_word_around_an_offset() {
+        let text = "hello, big_world 42";
+        assert_eq!(word_range(text, 0), 0..5);
+        assert_eq!(word_range(text, 3), 0..5);
+        assert_eq!(word_range(text, 8), 7..16);
+        assert_eq!(word_range(text, 12), 7..16);
+        assert_eq!(word_range(text, 17), 17..19);
+    }
+
+    #[test]
+    fn selects_a_single_character_between_words() {
+        let text = "hello, world";
+        assert_eq!(word_range(text, 5), 0..5);
+        assert_eq!(word_range(text, 6), 6..7);
+        assert_eq!(word_range("a -- b", 2), 2..3);
+        assert_eq!(word_range("né à", 3), 0..3);
+        assert_eq!(word_range("né à", 4), 4..6);
+    }
+
+    #[test]
+    fn selects_words_at_the_ends_of_the_text() {
+        assert_eq!(word_range("hello world", 11), 6..11);
+        assert_eq!(word_range("hello world", 42), 6..11);
+        assert_eq!(word_range("hello.", 6), 6..6);
+        assert_eq!(word_range("", 0), 0..0);
+    }
+
+    #[test]
+    fn selects_the_line_around_an_offset() {
+        let label = label("first line\nsecond\r\nlast");
+        assert_eq!(label.line_range(0), 0..10);
+        assert_eq!(label.line_range(4), 0..10);
+        assert_eq!(label.line_range(13), 11..17);
+        assert_eq!(label.line_range(21), 19..23);
+        assert_eq!(label.range_at(13, Granularity::Line), 11..17);
+        assert_eq!(label.range_at(13, Granularity::Word), 11..17);
+        assert_eq!(label.range_at(13, Granularity::Character), 13..13);
+    }
+
+    #[test]
+    fn selects_everything_without_a_layout() {
+        let mut label = SelectableLabel::new();
+        label.layout.set_text("one\ntwo".to_string());
+        assert_eq!(label.line_range(5), 0..7);
+    }
+}
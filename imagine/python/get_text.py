@@ -1,33 +1,115 @@
 import easyocr
 import sys
+import json
+import argparse
 from PIL import Image
-import csv
+
+# Version of the JSON schema, see src/ocr/protocol.rs
+PROTOCOL_VERSION = 1
+DEFAULT_LANGUAGES = ['fr', 'en']
+
+def document(result, size, languages):
+    return {
+        'version': PROTOCOL_VERSION,
+        'engine': 'easyocr',
+        'languages': languages,
+        'image': {'width': size[0], 'height': size[1]},
+        'boxes': [
+            {
+                'polygon': [[float(x), float(y)] for x, y in box],
+                'text': text,
+                'confidence': float(confidence),
+            }
+            for box, text, confidence in result
+        ],
+    }
+
+def to_legacy(result):
+    lines = []
+    for box, text, confidence in result:
+        p1, p2, p3, p4 = box
+        lines.append(f"({p1}-{p2}-{p3}-{p4});{text};{confidence}")
+    return '\n'.join(lines)
+
+def serve():
+    """Answer JSON-lines requests on stdin until it is closed"""
+    # Keep stdout for the protocol, easyocr progress messages go to stderr
+    out = sys.stdout
+    sys.stdout = sys.stderr
+    # Loading a model is slow: keep the reader until other options are requested
+    reader = None
+    reader_options = (DEFAULT_LANGUAGES, False)
+
+    def respond(request_id, result=None, error=None):
+        response = {'id': request_id}
+        if error is None:
+            response['result'] = result
+        else:
+            response['error'] = error
+        out.write(json.dumps(response) + '\n')
+        out.flush()
+
+    for line in sys.stdin:
+        if not line.strip():
+            continue
+        try:
+            request = json.loads(line)
+        except ValueError as e:
+            respond(None, error=f"invalid request: {e}")
+            continue
+
+        request_id = request.get('id')
+        method = request.get('method')
+        if method == 'health':
+            respond(request_id, result={
+                'status': 'idle' if reader is None else 'ready',
+                'engine': 'easyocr',
+                'languages': reader_options[0],
+            })
+        elif method == 'ocr':
+            try:
+                path = request['path']
+                options = (request.get('languages') or DEFAULT_LANGUAGES, bool(request.get('gpu', False)))
+                with Image.open(path) as img:
+                    size = img.size
+                if reader is None or options != reader_options:
+                    reader = easyocr.Reader(options[0], gpu=options[1])
+                    reader_options = options
+                result = reader.readtext(path, detail=1)
+                respond(request_id, result=document(result, size, options[0]))
+            except Exception as e:
+                respond(request_id, error=str(e))
+        else:
+            respond(request_id, error=f"unknown method {method!r}")
+    return 0
 
 def main():
-    # get the path argument
-    path = sys.argv[1]
+    parser = argparse.ArgumentParser()
+    parser.add_argument('--format', choices=['json', 'legacy'], default='json')
+    parser.add_argument('--server', action='store_true', help='answer JSON-lines requests on stdin')
+    parser.add_argument('--languages', default=','.join(DEFAULT_LANGUAGES), help='comma separated easyocr language codes')
+    parser.add_argument('--gpu', action='store_true', help='run the model on the gpu')
+    parser.add_argument('path', nargs='?')
+    args = parser.parse_args()
+    if args.server:
+        return serve()
+    if args.path is None:
+        parser.error('the image path is required')
+
     # check if the path is a file
     try:
-        with Image.open(path) as img:
-            reader = easyocr.Reader(['fr','en'], gpu=True) 
-            result = reader.readtext(path, detail=1)
-            # print to csv
-            for row in result:
-                box = row[0]
-                p1 = box[0]
-                p2 = box[1]
-                p3 = box[2]
-                p4 = box[3]
-
-                text = row[1]
-                confidence = row[2]
-                box = f"({p1}-{p2}-{p3}-{p4})"
-                print(box, text, confidence, sep=';')
-            
-            return 0    
+        with Image.open(args.path) as img:
+            languages = [code.strip() for code in args.languages.split(',') if code.strip()]
+            reader = easyocr.Reader(languages, gpu=args.gpu)
+            result = reader.readtext(args.path, detail=1)
+            if args.format == 'json':
+                print(json.dumps(document(result, img.size, languages)))
+            else:
+                print(to_legacy(result))
+            return 0
     except IOError:
         return 1
-    
+
 
 if __name__ == '__main__':
-    sys.exit(main())  
\ No newline at end of file
+    sys.exit(main())
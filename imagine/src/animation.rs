@@ -0,0 +1,275 @@
+//! Playback of animated GIF, PNG and WebP images
+//!
+//! Every frame is decoded when the image is opened, composed to the size of
+//! the image, and [`ImageState::image_buf`] is swapped to the next frame by a
+//! timer. The rest of the viewer only ever sees the current frame, so zoom,
+//! pan and the OCR overlay keep working during playback.
+
+use std::{
+    fs::File,
+    io::BufReader,
+    path::Path,
+    time::Duration,
+};
+
+use ::image::{
+    codecs::{gif::GifDecoder, png::PngDecoder, webp::WebPDecoder},
+    AnimationDecoder, DynamicImage, ImageFormat, ImageResult, RgbaImage,
+};
+use druid::{keyboard_types::Key, widget::Controller, TimerToken};
+
+use crate::prelude::*;
+
+use self::{image::ImageState, loader::{LoadError, MAX_PIXELS}, metadata::apply_orientation};
+
+/// Frames shorter than this are shown for [`SLOW_DELAY`], as web browsers do
+const MIN_DELAY: Duration = Duration::from_millis(20);
+const SLOW_DELAY: Duration = Duration::from_millis(100);
+/// Playback speeds, from the slowest
+pub const SPEEDS: [f64; 7] = [0.125, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0];
+
+/// A frame of an animation, as large as the image
+#[derive(Clone)]
+pub struct Frame {
+    pub image_buf: Arc<ImageBuf>,
+    /// How long the frame is shown at normal speed
+    pub delay: Duration,
+}
+
+/// The frames of an animated image and where the playback is
+#[derive(Clone, Data, Lens)]
+pub struct Animation {
+    pub frames: Arc<Vec<Frame>>,
+    /// Index of the shown frame
+    pub current: usize,
+    pub playing: bool,
+    /// One of [`SPEEDS`], 1 plays at the speed of the file
+    pub speed: f64,
+}
+
+impl Animation {
+    /// Play `frames` from the first one, at normal speed
+    pub fn new(frames: Vec<Frame>) -> Self {
+        Self { frames: Arc::new(frames), current: 0, playing: true, speed: 1.0 }
+    }
+
+    pub fn frame(&self) -> &Frame {
+        &self.frames[self.current]
+    }
+
+    /// Move `delta` frames away, wrapping around at the ends
+    pub fn step(&mut self, delta: isize) {
+        let len = self.frames.len() as isize;
+        self.current = (self.current as isize + delta).rem_euclid(len) as usize;
+    }
+
+    /// Time until the next frame at the current speed
+    pub fn delay(&self) -> Duration {
+        let delay = self.frame().delay;
+        let delay = if delay < MIN_DELAY { SLOW_DELAY } else { delay };
+        delay.div_f64(self.speed)
+    }
+
+    /// Go to the next speed of [`SPEEDS`], faster or slower
+    pub fn change_speed(&mut self, faster: bool) {
+        let index = SPEEDS.iter().position(|speed| *speed >= self.speed).unwrap_or(SPEEDS.len() - 1);
+        let index = if faster { (index + 1).min(SPEEDS.len() - 1) } else { index.saturating_sub(1) };
+        self.speed = SPEEDS[index];
+    }
+
+    /// The new frames of a reloaded image, played like the previous ones
+    pub fn continue_from(mut self, previous: &Animation) -> Self {
+        self.current = previous.current.min(self.frames.len() - 1);
+        self.playing = previous.playing;
+        self.speed = previous.speed;
+        self
+    }
+}
+
+impl ImageState {
+    /// Show the frame `delta` frames away, without changing the playback
+    pub fn step_frame(&mut self, delta: isize) {
+        if let Some(animation) = &mut self.animation {
+            animation.step(delta);
+            self.image_buf = animation.frame().image_buf.clone();
+        }
+    }
+
+    pub fn toggle_playback(&mut self) {
+        if let Some(animation) = &mut self.animation {
+            animation.playing = !animation.playing;
+        }
+    }
+
+    /// Stop the playback and step through the frames by hand
+    pub fn pause_and_step(&mut self, delta: isize) {
+        if let Some(animation) = &mut self.animation {
+            animation.playing = false;
+        }
+        self.step_frame(delta);
+    }
+
+    pub fn change_speed(&mut self, faster: bool) {
+        if let Some(animation) = &mut self.animation {
+            animation.change_speed(faster);
+        }
+    }
+}
+
+/// Every frame of the animated image at `path`, `None` if its format has no animations
+///
+/// A GIF with a single frame is still returned as one frame. Every frame is
+/// turned upright according to `orientation`, the EXIF orientation of the file.
+pub fn decode_frames(path: &Path, format: ImageFormat, width: u32, height: u32, orientation: u32) -> Result<Option<Vec<Frame>>, LoadError> {
+    let file = BufReader::new(File::open(path)?);
+    let frames = match format {
+        ImageFormat::Gif => GifDecoder::new(file)?.into_frames(),
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(file)?;
+            if !decoder.is_apng() {
+                return Ok(None);
+            }
+            decoder.apng().into_frames()
+        },
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(file)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            decoder.into_frames()
+        },
+        _ => return Ok(None),
+    };
+
+    let mut decoded = Vec::new();
+    let mut pixels = 0;
+    for frame in frames {
+        let frame = frame?;
+        // All the frames are kept in memory
+        pixels += width as u64 * height as u64;
+        if pixels > MAX_PIXELS {
+            return Err(LoadError::TooLarge { width, height });
+        }
+        let (numerator, denominator) = frame.delay().numer_denom_ms();
+        let delay = Duration::from_micros(numerator as u64 * 1000 / denominator.max(1) as u64);
+        let buffer = apply_orientation(DynamicImage::ImageRgba8(frame.into_buffer()), orientation).into_rgba8();
+        let (frame_width, frame_height) = (buffer.width() as usize, buffer.height() as usize);
+        let image_buf = ImageBuf::from_raw(buffer.into_raw(), druid::piet::ImageFormat::RgbaSeparate, frame_width, frame_height);
+        decoded.push(Frame { image_buf: Arc::new(image_buf), delay });
+    }
+    Ok(Some(decoded))
+}
+
+/// Save a frame as a PNG image
+pub fn save_frame(image_buf: &ImageBuf, path: &Path) -> ImageResult<()> {
+    let (width, height) = (image_buf.width() as u32, image_buf.height() as u32);
+    let image = RgbaImage::from_raw(width, height, image_buf.raw_pixels().to_vec()).expect("The frames are RGBA images");
+    image.save_with_format(path, ImageFormat::Png)
+}
+
+/// Plays the animation of the image state, and handles the playback keys
+///
+/// Space plays or pauses, `,` and `.` step through the frames, `[` and `]`
+/// change the speed.
+pub struct AnimationController {
+    timer: TimerToken,
+}
+
+impl Default for AnimationController {
+    fn default() -> Self {
+        Self { timer: TimerToken::INVALID }
+    }
+}
+
+impl AnimationController {
+    /// Delay before showing the next frame, if the animation is playing
+    fn next_delay(data: &ImageState) -> Option<Duration> {
+        data.animation.as_ref().filter(|animation| animation.playing).map(Animation::delay)
+    }
+}
+
+impl<W: Widget<ImageState>> Controller<ImageState, W> for AnimationController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut ImageState, env: &Env) {
+        if let Event::Timer(token) = event {
+            if *token == self.timer {
+                self.timer = TimerToken::INVALID;
+                data.step_frame(1);
+                ctx.set_handled();
+                return;
+            }
+        }
+        child.event(ctx, event, data, env);
+        if let Event::KeyDown(key_event) = event {
+            if ctx.is_handled() || data.animation.is_none() || key_event.mods.ctrl() || key_event.mods.alt() || key_event.mods.meta() {
+                return;
+            }
+            let Key::Character(key) = &key_event.key else {
+                return;
+            };
+            match key.as_str() {
+                " " => data.toggle_playback(),
+                "," => data.pause_and_step(-1),
+                "." => data.pause_and_step(1),
+                "[" => data.change_speed(false),
+                "]" => data.change_speed(true),
+                _ => return,
+            }
+            ctx.set_handled();
+        }
+    }
+
+    fn lifecycle(&mut self, child: &mut W, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &ImageState, env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            self.timer = Self::next_delay(data).map_or(TimerToken::INVALID, |delay| ctx.request_timer(delay));
+        }
+        child.lifecycle(ctx, event, data, env);
+    }
+
+    fn update(&mut self, child: &mut W, ctx: &mut UpdateCtx, old_data: &ImageState, data: &ImageState, env: &Env) {
+        // A new frame, image, speed or playback state: the pending timer is stale
+        if !old_data.animation.same(&data.animation) {
+            self.timer = Self::next_delay(data).map_or(TimerToken::INVALID, |delay| ctx.request_timer(delay));
+        }
+        child.update(ctx, old_data, data, env);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::image::{codecs::gif::GifEncoder, Delay, Rgba};
+
+    use super::*;
+
+    #[test]
+    fn decodes_the_frames_with_their_delays() {
+        let path = std::env::temp_dir().join(format!("imagine-animation-{}.gif", std::process::id()));
+        let frames = [(Rgba([255, 0, 0, 255]), 50), (Rgba([0, 0, 255, 255]), 200)].map(|(color, delay)| {
+            ::image::Frame::from_parts(RgbaImage::from_pixel(4, 3, color), 0, 0, Delay::from_numer_denom_ms(delay, 1))
+        });
+        GifEncoder::new(File::create(&path).unwrap()).encode_frames(frames).unwrap();
+
+        let frames = decode_frames(&path, ImageFormat::Gif, 4, 3, 1).unwrap().unwrap();
+        assert_eq!(frames.iter().map(|frame| frame.delay).collect::<Vec<_>>(), [Duration::from_millis(50), Duration::from_millis(200)]);
+        assert_eq!(&frames[1].image_buf.raw_pixels()[..4], [0, 0, 255, 255]);
+        assert_eq!(decode_frames(&path, ImageFormat::Jpeg, 4, 3, 1).unwrap().map(|frames| frames.len()), None);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn plays_in_a_loop() {
+        let frame = |delay| Frame { image_buf: Arc::new(ImageBuf::empty()), delay: Duration::from_millis(delay) };
+        let mut animation = Animation::new(vec![frame(0), frame(40), frame(80)]);
+        assert_eq!(animation.delay(), SLOW_DELAY);
+        animation.step(-1);
+        assert_eq!(animation.current, 2);
+        animation.step(2);
+        assert_eq!((animation.current, animation.delay()), (1, Duration::from_millis(40)));
+
+        animation.change_speed(true);
+        assert_eq!((animation.speed, animation.delay()), (2.0, Duration::from_millis(20)));
+        for _ in 0..10 {
+            animation.change_speed(false);
+        }
+        assert_eq!(animation.speed, SPEEDS[0]);
+    }
+}
@@ -0,0 +1,420 @@
+//! Headless OCR of many images, for scripts and CI
+//!
+//! `imagine ocr <files or globs>` runs the engine and the cache configured for
+//! the viewer on a bounded pool of threads, and prints the results on stdout
+//! in the order of the inputs, each as soon as it and the ones before it are
+//! done. Each page of a multi-page TIFF or ICO is recognized and printed on
+//! its own. Failures are reported on stderr and turn the exit status to 1.
+//!
+//! The pool overlaps the reading, the caching and the printing of the images
+//! with their recognition. How many images are recognized at once depends on
+//! the engine: tesseract runs a process per image, while the easyocr worker
+//! is a single process that answers one request at a time, so its requests
+//! are serialized whatever the number of jobs.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Write},
+    path::PathBuf,
+    str::FromStr,
+    sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, mpsc},
+    thread,
+};
+
+use serde::Serialize;
+
+use crate::{
+    cli::OcrArgs,
+    config::Config,
+    ocr::{cache::OcrCache, engine::{OcrEngine, OcrError}, jobs::recognize_cached, options::OcrOptions, protocol::OcrDocument, Ocr},
+    pages::page_count,
+};
+
+/// Exit status when every image was recognized
+pub const EXIT_SUCCESS: i32 = 0;
+/// Exit status when some images could not be recognized
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit status when the inputs are not usable
+pub const EXIT_USAGE: i32 = 2;
+
+/// How the results are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BatchFormat {
+    /// One JSON document per line, with the schema of the OCR helpers and the path of the image
+    #[default]
+    Json,
+    /// The text in reading order, under a header per image when there are several
+    Txt,
+    /// One row per word, with its position in the reading order and its bounding box
+    Tsv,
+}
+
+impl BatchFormat {
+    pub const ALL: [BatchFormat; 3] = [BatchFormat::Json, BatchFormat::Txt, BatchFormat::Tsv];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BatchFormat::Json => "json",
+            BatchFormat::Txt => "txt",
+            BatchFormat::Tsv => "tsv",
+        }
+    }
+}
+
+impl fmt::Display for BatchFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BatchFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BatchFormat::ALL
+            .into_iter()
+            .find(|format| format.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown output format `{}` (expected one of: json, txt, tsv)", s))
+    }
+}
+
+/// An image, or a page of an image with several pages, recognized on its own
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatchItem {
+    pub path: PathBuf,
+    /// Index of the page, `None` for the images with a single page
+    pub page: Option<usize>,
+}
+
+impl BatchItem {
+    /// `scan.tiff (page 2)`, the pages are counted from 1
+    pub fn label(&self) -> String {
+        match self.page {
+            Some(page) => format!("{} (page {})", self.path.display(), page + 1),
+            None => self.path.display().to_string(),
+        }
+    }
+}
+
+/// The items of `paths`, one per page of the images with several pages
+pub fn split_pages(paths: Vec<PathBuf>) -> Vec<BatchItem> {
+    let mut items = Vec::with_capacity(paths.len());
+    for path in paths {
+        match page_count(&path) {
+            1 => items.push(BatchItem { path, page: None }),
+            count => items.extend((0..count).map(|page| BatchItem { path: path.clone(), page: Some(page) })),
+        }
+    }
+    items
+}
+
+/// Line of the JSON output
+#[derive(Serialize)]
+struct JsonRecord<'a> {
+    path: &'a str,
+    /// Counted from 1, only for the images with several pages
+    #[serde(skip_serializing_if = "Option::is_none")]
+    page: Option<usize>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    document: Option<OcrDocument>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Prints the results of the images one after the other
+pub struct BatchWriter<W: Write> {
+    out: W,
+    format: BatchFormat,
+    /// Whether the text of each image gets a header
+    headers: bool,
+    /// Whether something was printed yet
+    started: bool,
+}
+
+impl<W: Write> BatchWriter<W> {
+    pub fn new(out: W, format: BatchFormat, inputs: usize) -> Self {
+        Self { out, format, headers: inputs > 1, started: false }
+    }
+
+    /// Print the result of an image or page
+    pub fn write(&mut self, item: &BatchItem, result: &Result<Ocr, OcrError>) -> io::Result<()> {
+        let path = &*item.path.to_string_lossy();
+        match (self.format, result) {
+            (BatchFormat::Json, result) => {
+                let record = JsonRecord {
+                    path,
+                    page: item.page.map(|page| page + 1),
+                    document: result.as_ref().ok().map(OcrDocument::from),
+                    error: result.as_ref().err().map(ToString::to_string),
+                };
+                serde_json::to_writer(&mut self.out, &record)?;
+                writeln!(self.out)?;
+            },
+            (BatchFormat::Txt, Ok(ocr)) => {
+                if self.started {
+                    writeln!(self.out)?;
+                }
+                if self.headers {
+                    writeln!(self.out, "==> {} <==", item.label())?;
+                }
+                writeln!(self.out, "{}", ocr.full_text())?;
+            },
+            (BatchFormat::Tsv, Ok(ocr)) => {
+                if !self.started {
+                    writeln!(self.out, "path\tpage\tblock\tline\tword\tleft\ttop\twidth\theight\tconfidence\ttext")?;
+                }
+                let mut line_num = 0;
+                for (block_num, block) in ocr.blocks().iter().enumerate() {
+                    for line in &block.lines {
+                        line_num += 1;
+                        for (word_num, word) in line.words.iter().enumerate() {
+                            let rect = word.bounding_rect().round();
+                            writeln!(
+                                self.out,
+                                "{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\t{:.3}\t{}",
+                                tsv_field(path),
+                                item.page.unwrap_or(0) + 1,
+                                block_num + 1,
+                                line_num,
+                                word_num + 1,
+                                rect.x0,
+                                rect.y0,
+                                rect.width(),
+                                rect.height(),
+                                word.confidence,
+                                tsv_field(&word.text),
+                            )?;
+                        }
+                    }
+                }
+            },
+            // Only reported on stderr
+            (_, Err(_)) => return Ok(()),
+        }
+        self.started = true;
+        self.out.flush()
+    }
+}
+
+/// Text without the characters that would break the rows and columns
+fn tsv_field(text: &str) -> String {
+    text.replace(['\t', '\n', '\r'], " ")
+}
+
+/// Paths of the inputs, glob patterns are expanded and sorted
+///
+/// Patterns that match nothing are errors, other paths are kept as they are
+/// and fail later if they cannot be read.
+pub fn expand_inputs(inputs: &[String]) -> Result<Vec<PathBuf>, String> {
+    let mut paths = Vec::new();
+    for input in inputs {
+        if !input.contains(['*', '?', '[']) {
+            paths.push(PathBuf::from(input));
+            continue;
+        }
+        let mut matches = glob::glob(input)
+            .map_err(|e| format!("invalid pattern `{}`: {}", input, e))?
+            .filter_map(Result::ok)
+            .filter(|path| path.is_file())
+            .collect::<Vec<_>>();
+        if matches.is_empty() {
+            return Err(format!("no file matches `{}`", input));
+        }
+        matches.sort();
+        paths.append(&mut matches);
+    }
+    Ok(paths)
+}
+
+/// Recognize `items` on `jobs` threads, calling `emit` with each result in the order of the items
+///
+/// The remaining images are skipped once `emit` returns `false`.
+pub fn recognize_all(
+    engine: &dyn OcrEngine,
+    cache: Option<&OcrCache>,
+    options: &OcrOptions,
+    force: bool,
+    items: &[BatchItem],
+    jobs: usize,
+    mut emit: impl FnMut(&BatchItem, Result<Ocr, OcrError>) -> bool,
+) {
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..jobs.clamp(1, items.len().max(1)) {
+            let sender = sender.clone();
+            let (next, stop) = (&next, &stop);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let i = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(i) else {
+                        break;
+                    };
+                    let result = match item.path.to_str() {
+                        Some(path) => recognize_cached(engine, cache, path, item.page.unwrap_or(0), options, force),
+                        None => Err(OcrError::InvalidPath),
+                    };
+                    if sender.send((i, result)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // Results finished early wait for the ones before them
+        let mut pending = BTreeMap::new();
+        let mut emitted = 0;
+        for (i, result) in receiver {
+            pending.insert(i, result);
+            while let Some(result) = pending.remove(&emitted) {
+                if !emit(&items[emitted], result) {
+                    stop.store(true, Ordering::Relaxed);
+                    return;
+                }
+                emitted += 1;
+            }
+        }
+    });
+}
+
+/// Run `imagine ocr`, returning the exit status
+pub fn run(args: &OcrArgs, config: &Config) -> i32 {
+    let paths = match expand_inputs(&args.inputs) {
+        Ok(paths) => paths,
+        Err(e) => {
+            eprintln!("imagine: {}", e);
+            return EXIT_USAGE;
+        },
+    };
+    let jobs = args
+        .jobs
+        .or_else(|| thread::available_parallelism().ok())
+        .map_or(1, usize::from);
+    let engine = config.build_engine();
+    let cache = config.build_cache();
+    let items = split_pages(paths);
+    let mut writer = BatchWriter::new(io::stdout().lock(), args.format, items.len());
+    let mut status = EXIT_SUCCESS;
+
+    recognize_all(engine.as_ref(), cache.as_ref(), &config.ocr_options, args.force, &items, jobs, |item, result| {
+        if let Err(e) = &result {
+            eprintln!("imagine: {}: OCR with {} failed: {}", item.label(), engine.name(), e);
+            status = EXIT_FAILURE;
+        }
+        match writer.write(item, &result) {
+            Ok(()) => true,
+            // The reader is gone, e.g. piped to `head`
+            Err(e) if e.kind() == io::ErrorKind::BrokenPipe => false,
+            Err(e) => {
+                eprintln!("imagine: failed to write the results: {}", e);
+                status = EXIT_FAILURE;
+                false
+            },
+        }
+    });
+    status
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use druid::Size;
+
+    use super::*;
+    use crate::ocr::mock::MockEngine;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("imagine-batch-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn output(format: BatchFormat, results: &[(&str, Result<Ocr, OcrError>)]) -> String {
+        let mut writer = BatchWriter::new(Vec::new(), format, results.len());
+        for (path, result) in results {
+            writer.write(&BatchItem { path: PathBuf::from(path), page: None }, result).unwrap();
+        }
+        String::from_utf8(writer.out).unwrap()
+    }
+
+    #[test]
+    fn writes_one_json_document_per_line() {
+        let ocr = Ocr::new("a.png", Vec::new()).with_engine("mock").with_image_size(Size::new(4.0, 2.0));
+        let json = output(BatchFormat::Json, &[("a.png", Ok(ocr)), ("b.png", Err(OcrError::InvalidPath))]);
+        let lines = json.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(r#"{"path":"a.png","version":1,"engine":"mock""#));
+        assert_eq!(lines[1], r#"{"path":"b.png","error":"image path is not valid UTF-8"}"#);
+    }
+
+    #[test]
+    fn runs_the_images_in_parallel_and_keeps_their_order() {
+        let dir = temp_dir("pool");
+        for name in ["b.png", "a.png", "c.png"] {
+            ::image::RgbImage::new(70, 70).save(dir.join(name)).unwrap();
+        }
+        fs::write(dir.join("notes.txt"), "not an image").unwrap();
+        let pattern = dir.join("*.png").to_string_lossy().into_owned();
+        let mut paths = expand_inputs(&[pattern]).unwrap();
+        paths.push(dir.join("missing.png"));
+        assert!(expand_inputs(&[dir.join("*.jpg").to_string_lossy().into_owned()]).is_err());
+
+        let items = split_pages(paths);
+        let mut writer = BatchWriter::new(Vec::new(), BatchFormat::Tsv, items.len());
+        let mut results = Vec::new();
+        recognize_all(&MockEngine, None, &OcrOptions::default(), false, &items, 3, |item, result| {
+            writer.write(item, &result).unwrap();
+            results.push((item.path.file_name().unwrap().to_string_lossy().into_owned(), result.is_ok()));
+            true
+        });
+        fs::remove_dir_all(dir).unwrap();
+
+        let expected = [("a.png", true), ("b.png", true), ("c.png", true), ("missing.png", false)];
+        assert_eq!(results, expected.map(|(name, ok)| (name.to_string(), ok)));
+        let tsv = String::from_utf8(writer.out).unwrap();
+        let rows = tsv.lines().collect::<Vec<_>>();
+        assert_eq!(rows.len(), 1 + 3 * 3);
+        assert!(rows[1].ends_with("a.png\t1\t1\t1\t1\t7\t10\t56\t10\t1.000\tMock line 1"), "{}", rows[1]);
+    }
+
+    #[test]
+    fn recognizes_every_page_of_a_tiff() {
+        let dir = temp_dir("pages");
+        let path = dir.join("scan.tiff");
+        let mut encoder = tiff::encoder::TiffEncoder::new(fs::File::create(&path).unwrap()).unwrap();
+        encoder.write_image::<tiff::encoder::colortype::Gray8>(70, 70, &[0; 70 * 70]).unwrap();
+        encoder.write_image::<tiff::encoder::colortype::Gray8>(80, 70, &[0; 80 * 70]).unwrap();
+        drop(encoder);
+
+        let items = split_pages(vec![path.clone()]);
+        assert_eq!(items.iter().map(|item| item.page).collect::<Vec<_>>(), [Some(0), Some(1)]);
+        let mut writer = BatchWriter::new(Vec::new(), BatchFormat::Json, items.len());
+        let mut pages = Vec::new();
+        recognize_all(&MockEngine, None, &OcrOptions::default(), false, &items, 2, |item, result| {
+            writer.write(item, &result).unwrap();
+            pages.push(result.unwrap().page);
+            true
+        });
+        fs::remove_dir_all(dir).unwrap();
+
+        assert_eq!(pages, [0, 1]);
+        let json = String::from_utf8(writer.out).unwrap();
+        assert!(json.lines().nth(1).unwrap().contains(r#""page":2"#), "{}", json);
+        assert_eq!(items[1].label(), format!("{} (page 2)", path.display()));
+    }
+
+    #[test]
+    fn separates_the_text_of_several_images() {
+        let ocr = |path: &str| Ocr::new(path, Vec::new());
+        assert_eq!(output(BatchFormat::Txt, &[("a.png", Ok(ocr("a.png")))]), "\n");
+        assert_eq!(
+            output(BatchFormat::Txt, &[("a.png", Ok(ocr("a.png"))), ("b.png", Ok(ocr("b.png")))]),
+            "==> a.png <==\n\n\n==> b.png <==\n\n"
+        );
+    }
+}
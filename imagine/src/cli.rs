@@ -0,0 +1,137 @@
+use std::{num::NonZeroUsize, path::PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+
+use crate::{batch::BatchFormat, export::ExportFormat, image::ZoomMode, navigation::scan_directory, ocr::{engine::EngineKind, options::OcrDevice}};
+
+/// Image viewer with selectable text
+#[derive(Debug, Parser)]
+#[command(name = "imagine", version, args_conflicts_with_subcommands = true)]
+pub struct Cli {
+    /// Images to open, or directories to open the images of
+    #[arg(value_name = "PATHS")]
+    pub paths: Vec<PathBuf>,
+
+    /// Zoom factor of the opened images, 1 shows them at their actual size
+    #[arg(long, value_parser = parse_zoom, conflicts_with = "fit")]
+    pub zoom: Option<f64>,
+
+    /// Fit the images to the window, enlarging the small ones
+    #[arg(long)]
+    pub fit: bool,
+
+    /// Do not recognize the text of the images when they are opened
+    #[arg(long)]
+    pub no_ocr: bool,
+
+    /// OCR backend to use (easyocr, tesseract or mock)
+    #[arg(long, global = true, env = "IMAGINE_OCR_ENGINE")]
+    pub ocr_engine: Option<EngineKind>,
+
+    /// Path of the easyocr helper script
+    #[arg(long, global = true, env = "IMAGINE_OCR_SCRIPT")]
+    pub ocr_script: Option<PathBuf>,
+
+    /// Comma separated languages to recognize (e.g. de,en)
+    #[arg(long, global = true, env = "IMAGINE_OCR_LANGUAGES")]
+    pub ocr_languages: Option<String>,
+
+    /// Run the OCR on the cpu or on the gpu
+    #[arg(long, global = true, env = "IMAGINE_OCR_DEVICE")]
+    pub ocr_device: Option<OcrDevice>,
+
+    /// Recognize the text of every page of IMAGE, write it to OUTPUT and exit, without opening a window
+    #[arg(long, num_args = 2, value_names = ["IMAGE", "OUTPUT"])]
+    pub export: Option<Vec<PathBuf>>,
+
+    /// Format of the export (txt, hocr, alto or pdf), guessed from the OUTPUT extension by default
+    #[arg(long, requires = "export")]
+    pub export_format: Option<ExportFormat>,
+
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+impl Cli {
+    /// The images to open, with the directories replaced by their supported images
+    pub fn images(&self) -> Vec<PathBuf> {
+        let mut images = Vec::new();
+        for path in &self.paths {
+            if !path.is_dir() {
+                images.push(path.clone());
+                continue;
+            }
+            match scan_directory(path) {
+                Ok(mut entries) => images.append(&mut entries),
+                Err(e) => eprintln!("Failed to read {}: {}", path.display(), e),
+            }
+        }
+        images
+    }
+
+    pub fn zoom_mode(&self) -> ZoomMode {
+        match self.zoom {
+            Some(factor) => ZoomMode::Factor(factor),
+            None if self.fit => ZoomMode::Fit,
+            None => ZoomMode::default(),
+        }
+    }
+}
+
+fn parse_zoom(value: &str) -> Result<f64, String> {
+    match value.trim().trim_end_matches('%').parse::<f64>() {
+        Ok(factor) if value.trim().ends_with('%') && factor > 0.0 && factor.is_finite() => Ok(factor / 100.0),
+        Ok(factor) if factor > 0.0 && factor.is_finite() => Ok(factor),
+        _ => Err(format!("`{}` is not a positive zoom factor (e.g. 1.5 or 150%)", value)),
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Recognize the text of images and print it, without opening a window
+    Ocr(OcrArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct OcrArgs {
+    /// Images to recognize, quoted glob patterns (e.g. 'shots/*.png') are expanded
+    #[arg(required = true, value_name = "FILES")]
+    pub inputs: Vec<String>,
+
+    /// Output format: json (one document per line), txt or tsv
+    #[arg(long, default_value_t = BatchFormat::Json)]
+    pub format: BatchFormat,
+
+    /// Number of images recognized at the same time, the number of cpus by default
+    ///
+    /// The easyocr engine is a single worker process that recognizes one image at a time.
+    #[arg(short, long)]
+    pub jobs: Option<NonZeroUsize>,
+
+    /// Run the engine even if the results are cached
+    #[arg(long)]
+    pub force: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tells_paths_from_the_ocr_command() {
+        let cli = Cli::try_parse_from(["imagine", "shot.png", "--zoom", "150%", "--no-ocr"]).unwrap();
+        assert_eq!(cli.paths, vec![PathBuf::from("shot.png")]);
+        assert_eq!(cli.zoom_mode(), ZoomMode::Factor(1.5));
+        assert!(cli.no_ocr && cli.command.is_none());
+
+        let cli = Cli::try_parse_from(["imagine", "ocr", "--ocr-engine", "mock", "a.png"]).unwrap();
+        assert!(cli.paths.is_empty() && matches!(cli.command, Some(Command::Ocr(_))));
+        assert_eq!(cli.ocr_engine, Some(EngineKind::Mock));
+
+        assert!(Cli::try_parse_from(["imagine", "--fit", "--zoom", "2"]).is_err());
+        assert!(Cli::try_parse_from(["imagine", "--zoom", "0"]).is_err());
+        for zoom in ["inf", "inf%", "1e400%", "NaN%", "0%"] {
+            assert!(Cli::try_parse_from(["imagine", "--zoom", zoom]).is_err(), "{}", zoom);
+        }
+    }
+}
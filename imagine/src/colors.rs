@@ -0,0 +1,21 @@
+use druid::Color;
+
+/// Inside of the region being dragged for OCR
+pub const REGION_FILL: Color = Color::rgba8(0x33, 0x99, 0xff, 0x40);
+/// Border of the region being dragged for OCR
+pub const REGION_BORDER: Color = Color::rgb8(0x33, 0x99, 0xff);
+/// Search hits over the image
+pub const SEARCH_MATCH: Color = Color::rgba8(0xff, 0xd7, 0x00, 0x60);
+/// Selected search hit
+pub const SEARCH_CURRENT: Color = Color::rgba8(0xff, 0x8c, 0x00, 0x90);
+/// Background of the tooltip of the hovered box
+pub const TOOLTIP_BACKGROUND: Color = Color::rgba8(0x20, 0x20, 0x20, 0xe0);
+pub const TOOLTIP_TEXT: Color = Color::WHITE;
+
+/// Outline of an OCR box, from red for 0 to yellow for 0.5 and green for 1
+pub fn confidence_color(confidence: f64) -> Color {
+    let confidence = confidence.clamp(0.0, 1.0);
+    let red = (2.0 * (1.0 - confidence)).min(1.0);
+    let green = (2.0 * confidence).min(1.0);
+    Color::rgb(red, green, 0.0)
+}
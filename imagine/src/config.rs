@@ -0,0 +1,141 @@
+use std::{fs, path::PathBuf};
+
+use crate::{cli::Cli, ocr::{engine::{EngineKind, OcrEngine}, easyocr::EasyOcrEngine, tesseract::TesseractEngine, mock::MockEngine, cache::{self, OcrCache}, options::OcrOptions}};
+use crate::prelude::*;
+
+/// User configuration, read from `$XDG_CONFIG_HOME/imagine/config`
+///
+/// The file is made of `key = value` lines, `#` starts a comment:
+///
+/// ```text
+/// ocr_engine = tesseract
+/// python = python3
+/// ocr_script = /usr/share/imagine/get_text.py
+/// tesseract = /usr/bin/tesseract
+/// ocr_languages = de, en
+/// ocr_device = gpu
+/// cache_dir = /tmp/imagine-ocr
+/// cache_size_mb = 64 # 0 disables the cache
+/// ```
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub ocr_engine: EngineKind,
+    pub python: String,
+    pub ocr_script: Option<PathBuf>,
+    pub tesseract: String,
+    pub ocr_options: OcrOptions,
+    pub cache_dir: Option<PathBuf>,
+    pub cache_size_mb: u64,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            ocr_engine: EngineKind::default(),
+            python: "python3".to_string(),
+            ocr_script: None,
+            tesseract: "tesseract".to_string(),
+            ocr_options: OcrOptions::default(),
+            cache_dir: OcrCache::default_dir(),
+            cache_size_mb: cache::DEFAULT_MAX_BYTES / (1024 * 1024),
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration file (if any) and apply the command line overrides
+    pub fn load(cli: &Cli) -> Self {
+        let mut config = Self::default();
+        if let Some(path) = Self::path() {
+            match fs::read_to_string(&path) {
+                Ok(content) => config.parse(&content),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {},
+                Err(e) => eprintln!("Failed to read {}: {}", path.display(), e),
+            }
+        }
+
+        if let Some(engine) = cli.ocr_engine {
+            config.ocr_engine = engine;
+        }
+        if let Some(script) = &cli.ocr_script {
+            config.ocr_script = Some(script.clone());
+        }
+        if let Some(languages) = &cli.ocr_languages {
+            match OcrOptions::parse_languages(languages) {
+                Ok(languages) => config.ocr_options.languages = languages,
+                Err(e) => eprintln!("Ignoring --ocr-languages: {}", e),
+            }
+        }
+        if let Some(device) = cli.ocr_device {
+            config.ocr_options.device = device;
+        }
+        config
+    }
+
+    /// Location of the configuration file
+    pub fn path() -> Option<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+        Some(config_dir.join("imagine").join("config"))
+    }
+
+    fn parse(&mut self, content: &str) {
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                eprintln!("Ignoring invalid config line: {}", line);
+                continue;
+            };
+            let value = value.trim();
+            match key.trim() {
+                "ocr_engine" => match value.parse() {
+                    Ok(engine) => self.ocr_engine = engine,
+                    Err(e) => eprintln!("Ignoring config: {}", e),
+                },
+                "python" => self.python = value.to_string(),
+                "ocr_script" => self.ocr_script = Some(PathBuf::from(value)),
+                "tesseract" => self.tesseract = value.to_string(),
+                "ocr_languages" => match OcrOptions::parse_languages(value) {
+                    Ok(languages) => self.ocr_options.languages = languages,
+                    Err(e) => eprintln!("Ignoring config: {}", e),
+                },
+                "ocr_device" => match value.parse() {
+                    Ok(device) => self.ocr_options.device = device,
+                    Err(e) => eprintln!("Ignoring config: {}", e),
+                },
+                "cache_dir" => self.cache_dir = Some(PathBuf::from(value)),
+                "cache_size_mb" => match value.parse() {
+                    Ok(size) => self.cache_size_mb = size,
+                    Err(e) => eprintln!("Ignoring config: invalid cache_size_mb `{}`: {}", value, e),
+                },
+                key => eprintln!("Ignoring unknown config key: {}", key),
+            }
+        }
+    }
+
+    /// Build the OCR engine selected by this configuration
+    pub fn build_engine(&self) -> Arc<dyn OcrEngine> {
+        match self.ocr_engine {
+            EngineKind::EasyOcr => {
+                let script = self.ocr_script.clone().unwrap_or_else(EasyOcrEngine::find_script);
+                Arc::new(EasyOcrEngine::new(self.python.clone(), script))
+            },
+            EngineKind::Tesseract => Arc::new(TesseractEngine::new(self.tesseract.clone())),
+            EngineKind::Mock => Arc::new(MockEngine),
+        }
+    }
+
+    /// Build the OCR result cache, unless it is disabled
+    pub fn build_cache(&self) -> Option<OcrCache> {
+        if self.cache_size_mb == 0 {
+            return None;
+        }
+        let dir = self.cache_dir.clone()?;
+        Some(OcrCache::new(dir, self.cache_size_mb * 1024 * 1024))
+    }
+}
@@ -1,21 +1,91 @@
-use druid::{AppDelegate, DelegateCtx, Command, Target, Env, commands, Handled, Selector, keyboard_types::Key};
+use druid::{AppDelegate, Application, DelegateCtx, Command, FileInfo, Target, Env, commands, Handled, Rect, Selector, WindowConfig};
 
-use crate::{prelude::*, dialog::open_image_dialog};
+use std::path::Path;
 
-use self::{image::ImageStateTrait, ocr::Ocr};
+use crate::{prelude::*, dialog::open_image_dialog, export::ExportFormat};
+
+use self::{image::{ImageStateTrait, ImageStatus}, loader::{ImageLoader, LoadError, LoadedImage}, navigation::NavigationStep, startup::Startup, watcher::{FileChanges, FileWatcher}, ocr::{Ocr, cache::OcrCache, engine::OcrEngine, jobs::{OcrFailure, OcrJobManager, RegionOcr}}};
 
 pub const CTRL: Selector<bool> = Selector::new("imagine.ctrl_pressed");
 pub const SEND_OCR: Selector<Ocr> = Selector::new("imagine.send_ocr");
 pub const RESET_OCR: Selector<()> = Selector::new("imagine.reset_ocr");
+pub const OCR_FAILED: Selector<OcrFailure> = Selector::new("imagine.ocr_failed");
+pub const COPY_ALL_TEXT: Selector<()> = Selector::new("imagine.copy_all_text");
+pub const FORCE_OCR: Selector<()> = Selector::new("imagine.force_ocr");
+pub const RUN_OCR: Selector<()> = Selector::new("imagine.run_ocr");
+/// OCR a region of the current image, in image coordinates
+pub const OCR_REGION: Selector<Rect> = Selector::new("imagine.ocr_region");
+pub const SEND_REGION_OCR: Selector<RegionOcr> = Selector::new("imagine.send_region_ocr");
+pub const OPEN_FIND: Selector<()> = Selector::new("imagine.open_find");
+pub const CLOSE_FIND: Selector<()> = Selector::new("imagine.close_find");
+/// Give the keyboard focus to the find bar
+pub const FOCUS_FIND: Selector<()> = Selector::new("imagine.focus_find");
+/// Give the keyboard focus back to the image view, for the navigation keys
+pub const FOCUS_IMAGE: Selector<()> = Selector::new("imagine.focus_image");
+/// Select the search hit that many positions away and show it
+pub const FIND_STEP: Selector<isize> = Selector::new("imagine.find_step");
+/// Scroll the image view to `ImageState::center`
+pub const CENTER_IMAGE: Selector<()> = Selector::new("imagine.center_image");
+/// Open another image of the navigation list
+pub const NAVIGATE: Selector<NavigationStep> = Selector::new("imagine.navigate");
+/// Files changed in the folder of the current image
+pub const FILES_CHANGED: Selector<FileChanges> = Selector::new("imagine.files_changed");
+/// An image was decoded by the loader thread
+pub const IMAGE_LOADED: Selector<LoadedImage> = Selector::new("imagine.image_loaded");
+/// Save the current frame of the animation as a PNG image
+pub const SAVE_FRAME: Selector<FileInfo> = Selector::new("imagine.save_frame");
+/// Show a page of the current document
+pub const SHOW_PAGE: Selector<usize> = Selector::new("imagine.show_page");
+/// Recognize the text of every page of the current document
+pub const OCR_ALL_PAGES: Selector<()> = Selector::new("imagine.ocr_all_pages");
+/// A file could not be written by a background thread, with the reason
+pub const SAVE_FAILED: Selector<String> = Selector::new("imagine.save_failed");
 pub struct Delegate {
     window_size: Size,
+    ocr_jobs: OcrJobManager,
+    loader: ImageLoader,
+    startup: Startup,
+    watcher: FileWatcher,
+    /// Generation of the OCR of all the pages, the other pages are not recognized while it runs
+    pages_job: Option<u64>,
 }
 
-impl Default for Delegate {
-    fn default() -> Self {
+impl Delegate {
+    pub fn new(ocr_engine: Arc<dyn OcrEngine>, ocr_cache: Option<OcrCache>) -> Self {
         Self {
             window_size: Size::new(1.0, 1.0),
+            ocr_jobs: OcrJobManager::new(ocr_engine, ocr_cache),
+            loader: ImageLoader::default(),
+            startup: Startup::default(),
+            watcher: FileWatcher::default(),
+            pages_job: None,
+        }
+    }
+
+    /// Recognize the text of the new image, if asked to when opening images
+    fn auto_ocr(&self, ctx: &mut DelegateCtx, data: &AppState) {
+        if data.auto_ocr {
+            let page = data.image_state.current_page();
+            self.ocr_jobs.submit(data.image_state.path.clone(), page, data.ocr_options.clone(), ctx.get_external_handle());
+        } else {
+            self.ocr_jobs.cancel();
+        }
+    }
+
+    /// Show why the image at `path` cannot be opened, or let the user pick another one at startup
+    fn show_load_error(&mut self, ctx: &mut DelegateCtx, target: Target, data: &mut AppState, path: &str, error: LoadError) {
+        if self.startup.is_pending() {
+            // The error panel is hidden with the window, let the user pick another image first
+            eprintln!("Failed to open {}: {}", path, error);
+            self.startup = Startup::Choosing;
+            ctx.submit_command(commands::SHOW_OPEN_PANEL.with(open_image_dialog()).to(target));
         }
+        data.image_state.show_error(path, error);
+        self.ocr_jobs.cancel();
+    }
+
+    fn is_recognizing_all_pages(&self) -> bool {
+        self.pages_job == Some(self.ocr_jobs.generation())
     }
 }
 
@@ -23,18 +93,233 @@ impl AppDelegate<AppState> for Delegate {
     fn command(
         &mut self,
         ctx: &mut DelegateCtx,
-        _target: Target,
+        target: Target,
         cmd: &Command,
         data: &mut AppState,
         _env: &Env,
     ) -> Handled {
         if let Some(file_info) = cmd.get(commands::OPEN_FILE) {
-            // Get the window size
+            // The previous image stays on screen until the new one is decoded
+            let Some(path) = file_info.path().to_str() else {
+                let path = file_info.path().to_string_lossy().into_owned();
+                self.show_load_error(ctx, target, data, &path, LoadError::InvalidPath);
+                return Handled::Yes;
+            };
+            data.navigation.select(path);
+            data.image_state.status = ImageStatus::Loading(path.to_string());
+            self.loader.load(path, target, ctx.get_external_handle());
+            return Handled::Yes;
+        } else if let Some(loaded) = cmd.get(IMAGE_LOADED) {
+            if loaded.reload {
+                // Another image may have been opened since
+                if loaded.path == data.image_state.path && data.image_state.status == ImageStatus::Ready {
+                    match &loaded.result {
+                        Ok(image) => {
+                            data.image_state.reload(image.clone());
+                            ctx.submit_command(CENTER_IMAGE);
+                            self.auto_ocr(ctx, data);
+                        },
+                        // Probably still being written, the next change reloads it
+                        Err(e) => eprintln!("Failed to reload {}: {}", loaded.path, e),
+                    }
+                }
+                return Handled::Yes;
+            }
+            if data.image_state.status != ImageStatus::Loading(loaded.path.clone()) {
+                return Handled::Yes;
+            }
+            // The folder is watched even after an error, to retry once the file is fixed
+            self.watcher.watch(Path::new(&loaded.path), ctx.get_external_handle());
+            let image = match &loaded.result {
+                Ok(image) => image.clone(),
+                Err(e) => {
+                    self.show_load_error(ctx, target, data, &loaded.path, e.clone());
+                    return Handled::Yes;
+                },
+            };
+            data.image_state.change_image(&loaded.path, image, self.window_size);
+            self.pages_job = None;
+            if self.startup.is_pending() {
+                let image_size = data.image_state.image_buf.size();
+                self.window_size = startup::window_size_for(image_size, startup::work_area());
+                data.image_state.reset_zoom(self.window_size);
+                ctx.submit_command(commands::CONFIGURE_WINDOW.with(WindowConfig::default().window_size(self.window_size)).to(target));
+                ctx.submit_command(commands::SHOW_WINDOW.to(target));
+                self.startup = Startup::Ready;
+            }
+            ctx.submit_command(CENTER_IMAGE);
+            self.auto_ocr(ctx, data);
+            return Handled::Yes;
+        } else if let Some(step) = cmd.get(NAVIGATE) {
+            // PageUp and PageDown go through the pages before the other images
+            let page = data.image_state.document.as_ref().and_then(|document| document.target(*step));
+            if let Some(page) = page {
+                ctx.submit_command(SHOW_PAGE.with(page));
+            } else if let Some(path) = data.navigation.target(*step) {
+                ctx.submit_command(commands::OPEN_FILE.with(FileInfo { path: path.into(), format: None }).to(target));
+            }
+            return Handled::Yes;
+        } else if let Some(page) = cmd.get(SHOW_PAGE) {
+            // The pages of a TIFF may have different sizes, the others keep the zoom
+            if data.image_state.show_page(*page) {
+                data.image_state.reset_zoom(self.window_size);
+                ctx.submit_command(CENTER_IMAGE);
+            }
+            // Don't stop the OCR of all the pages, the result of this one is on its way
+            if data.image_state.ocr.is_none() && !self.is_recognizing_all_pages() {
+                self.auto_ocr(ctx, data);
+            }
+            return Handled::Yes;
+        } else if let Some(changes) = cmd.get(FILES_CHANGED) {
+            if changes.listing_changed {
+                data.navigation.refresh();
+            }
+            let path = &data.image_state.path;
+            if !path.is_empty() && changes.contains(Path::new(path)) {
+                match data.image_state.status {
+                    ImageStatus::Ready => self.loader.reload(path.clone(), target, ctx.get_external_handle()),
+                    // The file may be readable now
+                    ImageStatus::Error(_) => {
+                        data.image_state.status = ImageStatus::Loading(path.clone());
+                        self.loader.load(path.clone(), target, ctx.get_external_handle());
+                    },
+                    ImageStatus::Loading(_) => {},
+                }
+            }
+            return Handled::Yes;
+        } else if cmd.is(commands::OPEN_PANEL_CANCELLED) {
+            // Nothing to show: quit instead of staying as an invisible process
+            if self.startup == Startup::Choosing {
+                ctx.submit_command(commands::QUIT_APP);
+            }
+            return Handled::Yes;
+        } else if let Some(file_info) = cmd.get(commands::SAVE_FILE_AS) {
+            // Every recognized page of a document goes to the same file
+            let pages = data.image_state.recognized_pages();
+            if pages.is_empty() {
+                return Handled::Yes;
+            }
+            let mut path = file_info.path().to_path_buf();
+            let format = ExportFormat::from_path(&path).unwrap_or_default();
+            if path.extension().is_none() {
+                path.set_extension(format.extensions()[0]);
+            }
+            // Embedding the image in a PDF takes a while
             let handle = ctx.get_external_handle();
-            data.image_state.change_image(file_info.path().to_str().unwrap(), self.window_size, handle);
-            // Show the window now that we have an image
+            std::thread::spawn(move || {
+                if let Err(e) = export::export(&pages, format, &path) {
+                    let message = format!("Failed to export {}: {}", path.display(), e);
+                    // Nobody to tell if the app quit meanwhile
+                    let _ = handle.submit_command(SAVE_FAILED, message, Target::Auto);
+                }
+            });
             return Handled::Yes;
-        } 
+        } else if let Some(file_info) = cmd.get(SAVE_FRAME) {
+            let mut path = file_info.path().to_path_buf();
+            if path.extension().is_none() {
+                path.set_extension("png");
+            }
+            let image_buf = data.image_state.image_buf.clone();
+            let handle = ctx.get_external_handle();
+            std::thread::spawn(move || {
+                if let Err(e) = animation::save_frame(&image_buf, &path) {
+                    let message = format!("Failed to save the frame to {}: {}", path.display(), e);
+                    let _ = handle.submit_command(SAVE_FAILED, message, Target::Auto);
+                }
+            });
+            return Handled::Yes;
+        } else if let Some(message) = cmd.get(SAVE_FAILED) {
+            data.image_state.task_error = Some(message.clone());
+            return Handled::Yes;
+        } else if let Some(failure) = cmd.get(OCR_FAILED) {
+            if failure.path == data.image_state.path {
+                data.image_state.task_error = Some(failure.message.clone());
+            }
+            return Handled::Yes;
+        } else if let Some(ocr) = cmd.get(SEND_OCR) {
+            // A result of a previous image may still arrive after a switch
+            if ocr.img_path != data.image_state.path {
+                return Handled::Yes;
+            }
+            for diagnostic in &ocr.diagnostics {
+                eprintln!("Skipped OCR output {}", diagnostic);
+            }
+            data.image_state.task_error = None;
+            data.image_state.set_ocr(ocr.clone());
+        } else if let Some(region) = cmd.get(OCR_REGION) {
+            if !data.image_state.path.is_empty() {
+                let path = data.image_state.path.clone();
+                let page = data.image_state.current_page();
+                self.ocr_jobs.submit_region(path, page, *region, data.ocr_options.clone(), ctx.get_external_handle());
+            }
+            return Handled::Yes;
+        } else if let Some(RegionOcr { region, ocr }) = cmd.get(SEND_REGION_OCR) {
+            if ocr.img_path != data.image_state.path || ocr.page != data.image_state.current_page() {
+                return Handled::Yes;
+            }
+            for diagnostic in &ocr.diagnostics {
+                eprintln!("Skipped OCR output {}", diagnostic);
+            }
+            data.image_state.task_error = None;
+            // Keep the text found outside of the region
+            let merged = match data.image_state.ocr.clone() {
+                Some(mut current) => {
+                    current.merge_region(*region, ocr.clone());
+                    current
+                },
+                None => ocr.clone(),
+            };
+            data.image_state.search.refresh(Some(&merged));
+            data.image_state.ocr = Some(merged);
+        } else if cmd.is(OPEN_FIND) {
+            data.image_state.search.open = true;
+            let ocr = data.image_state.ocr.clone();
+            data.image_state.search.refresh(ocr.as_ref());
+            ctx.submit_command(FOCUS_FIND);
+            return Handled::Yes;
+        } else if cmd.is(CLOSE_FIND) {
+            data.image_state.search.open = false;
+            data.image_state.search.refresh(None);
+            ctx.submit_command(FOCUS_IMAGE);
+            return Handled::Yes;
+        } else if let Some(delta) = cmd.get(FIND_STEP) {
+            data.image_state.search.step(*delta);
+            if data.image_state.search.current_match().is_some() {
+                data.image_state.show_match(self.window_size);
+                ctx.submit_command(CENTER_IMAGE);
+            }
+            return Handled::Yes;
+        } else if cmd.is(RESET_OCR) {
+            self.ocr_jobs.cancel();
+            data.image_state.ocr = None;
+        } else if cmd.is(FORCE_OCR) || cmd.is(RUN_OCR) {
+            // RUN_OCR follows a change of the options, which are part of the cache key
+            if !data.image_state.path.is_empty() {
+                let path = data.image_state.path.clone();
+                let page = data.image_state.current_page();
+                let handle = ctx.get_external_handle();
+                if cmd.is(FORCE_OCR) {
+                    self.ocr_jobs.force(path, page, data.ocr_options.clone(), handle);
+                } else {
+                    self.ocr_jobs.submit(path, page, data.ocr_options.clone(), handle);
+                }
+            }
+            return Handled::Yes;
+        } else if cmd.is(OCR_ALL_PAGES) {
+            if let Some(document) = &data.image_state.document {
+                let path = data.image_state.path.clone();
+                let count = document.pages.len();
+                self.pages_job = Some(self.ocr_jobs.submit_pages(path, count, data.ocr_options.clone(), ctx.get_external_handle()));
+            }
+            return Handled::Yes;
+        } else if cmd.is(COPY_ALL_TEXT) {
+            let pages = data.image_state.recognized_pages();
+            if !pages.is_empty() {
+                let text = pages.iter().map(Ocr::full_text).collect::<Vec<_>>().join("\n\n");
+                Application::global().clipboard().put_string(text);
+            }
+            return Handled::Yes;
+        }
         Handled::No
     }
 
@@ -43,16 +328,22 @@ impl AppDelegate<AppState> for Delegate {
             ctx: &mut DelegateCtx,
             window_id: WindowId,
             event: Event,
-            _data: &mut AppState,
+            data: &mut AppState,
             _env: &Env,
         ) -> Option<Event> {
             match &event {
-                Event::WindowConnected => {
+                Event::WindowConnected if self.startup == Startup::Connecting => {
                     // Hide the window until we have an image
-                    //ctx.submit_command(commands::HIDE_WINDOW.to(window_id));
-                    ctx.submit_command(commands::SHOW_OPEN_PANEL.with(open_image_dialog()).to(window_id));
-                    ctx.submit_command(RESET_OCR);
                     ctx.submit_command(commands::HIDE_WINDOW.to(window_id));
+                    ctx.submit_command(RESET_OCR);
+                    if let Some(path) = data.navigation.current_file() {
+                        self.startup = Startup::Opening;
+                        let file_info = FileInfo { path: path.into(), format: None };
+                        ctx.submit_command(commands::OPEN_FILE.with(file_info).to(window_id));
+                    } else {
+                        self.startup = Startup::Choosing;
+                        ctx.submit_command(commands::SHOW_OPEN_PANEL.with(open_image_dialog()).to(window_id));
+                    }
                     Some(event)
                 },
                 Event::WindowSize(size) => {
@@ -1,17 +1,58 @@
+use std::path::Path;
+
 use druid::{FileDialogOptions, FileSpec};
 
+use crate::{delegate::SAVE_FRAME, export::ExportFormat};
+
+/// Image formats that can be opened
+pub const IMAGE_TYPES: [FileSpec; 7] = [
+    FileSpec::new("PNG", &["png"]),
+    FileSpec::new("JPG", &["jpg", "jpeg"]),
+    FileSpec::new("BMP", &["bmp"]),
+    FileSpec::new("GIF", &["gif"]),
+    FileSpec::new("WebP", &["webp"]),
+    FileSpec::new("ICO", &["ico"]),
+    FileSpec::new("TIFF", &["tiff", "tif"]),
+];
+
+/// Whether the extension of `path` is one of [`IMAGE_TYPES`]
+pub fn is_supported_image(path: &Path) -> bool {
+    let Some(extension) = path.extension().and_then(|extension| extension.to_str()) else {
+        return false;
+    };
+    IMAGE_TYPES.iter().flat_map(|spec| spec.extensions).any(|known| known.eq_ignore_ascii_case(extension))
+}
+
 pub fn open_image_dialog() -> FileDialogOptions {
     FileDialogOptions::new()
         .name_label("Open")
         .title("Open")
         .button_text("Open")
-        .allowed_types(vec![
-            FileSpec::new("PNG", &["png"]),
-            FileSpec::new("JPG", &["jpg", "jpeg"]),
-            FileSpec::new("BMP", &["bmp"]),
-            FileSpec::new("GIF", &["gif"]),
-            FileSpec::new("ICO", &["ico"]),
-            FileSpec::new("TIFF", &["tiff"]),
+        .allowed_types(IMAGE_TYPES.to_vec())
+}
 
-        ])
+/// Save dialog of the current frame of an animation
+pub fn frame_dialog(image_path: &str, frame: usize) -> FileDialogOptions {
+    let png = FileSpec::new("PNG", &["png"]);
+    let name = Path::new(image_path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    FileDialogOptions::new()
+        .title("Export the current frame")
+        .button_text("Export")
+        .default_name(format!("{}-frame{}.png", name, frame + 1))
+        .allowed_types(vec![png])
+        .default_type(png)
+        .accept_command(SAVE_FRAME)
+}
+
+/// Save dialog of the OCR export, named after the image at `image_path`
+pub fn export_dialog(image_path: &str) -> FileDialogOptions {
+    let types = ExportFormat::ALL.map(|format| FileSpec::new(format.label(), format.extensions()));
+    let name = Path::new(image_path).file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+    FileDialogOptions::new()
+        .name_label("Export")
+        .title("Export text")
+        .button_text("Export")
+        .default_name(format!("{}.{}", name, ExportFormat::default().extensions()[0]))
+        .allowed_types(types.to_vec())
+        .default_type(types[0])
 }
@@ -0,0 +1,244 @@
+//! Export of the OCR results to files other tools can read
+//!
+//! Plain text follows the reading order of [`Ocr::full_text`], hOCR and ALTO
+//! keep the blocks, lines and words with their bounding boxes, and the PDF
+//! embeds the image under an invisible text layer, so the page looks like the
+//! image but its text can be searched and selected.
+
+use std::{
+    fmt, fs, io,
+    path::Path,
+    str::FromStr,
+};
+
+use druid::{Rect, Size};
+
+use crate::{
+    ocr::{
+        engine::{OcrEngine, OcrError},
+        jobs::recognize_cached,
+        options::OcrOptions,
+        Ocr,
+    },
+    pages::page_count,
+};
+
+pub mod alto;
+pub mod hocr;
+pub mod pdf;
+
+/// The file formats the OCR results can be written to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ExportFormat {
+    #[default]
+    Text,
+    Hocr,
+    Alto,
+    Pdf,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 4] = [ExportFormat::Text, ExportFormat::Hocr, ExportFormat::Alto, ExportFormat::Pdf];
+
+    /// Name of the format in the save dialog
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Text => "Plain text",
+            ExportFormat::Hocr => "hOCR",
+            ExportFormat::Alto => "ALTO XML",
+            ExportFormat::Pdf => "Searchable PDF",
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Text => "txt",
+            ExportFormat::Hocr => "hocr",
+            ExportFormat::Alto => "alto",
+            ExportFormat::Pdf => "pdf",
+        }
+    }
+
+    /// Extensions of the format, the usual one first
+    pub fn extensions(&self) -> &'static [&'static str] {
+        match self {
+            ExportFormat::Text => &["txt"],
+            ExportFormat::Hocr => &["hocr", "html"],
+            ExportFormat::Alto => &["xml", "alto"],
+            ExportFormat::Pdf => &["pdf"],
+        }
+    }
+
+    /// Format of a file, guessed from its extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        ExportFormat::ALL.into_iter().find(|format| format.extensions().contains(&extension.as_str()))
+    }
+}
+
+impl fmt::Display for ExportFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        ExportFormat::ALL
+            .into_iter()
+            .find(|format| format.name() == s || format.extensions().contains(&s.as_str()))
+            .ok_or_else(|| format!("unknown export format `{}` (expected one of: txt, hocr, alto, pdf)", s))
+    }
+}
+
+/// Error raised when the results could not be exported
+#[derive(Debug)]
+pub enum ExportError {
+    /// The output file could not be written
+    Io(io::Error),
+    /// The image could not be read or encoded, for the PDF
+    Image(String),
+    /// The text of the image could not be recognized
+    Ocr(OcrError),
+}
+
+impl fmt::Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Io(e) => write!(f, "failed to write the export: {}", e),
+            ExportError::Image(e) => write!(f, "failed to embed the image: {}", e),
+            ExportError::Ocr(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ExportError::Io(e) => Some(e),
+            ExportError::Ocr(e) => Some(e),
+            ExportError::Image(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+/// Content of the export of `pages`, one `Ocr` per page
+pub fn render(pages: &[Ocr], format: ExportFormat) -> Result<Vec<u8>, ExportError> {
+    Ok(match format {
+        ExportFormat::Text => {
+            let mut text = pages.iter().map(Ocr::full_text).collect::<Vec<_>>().join("\n\x0c\n");
+            text.push('\n');
+            text.into_bytes()
+        },
+        ExportFormat::Hocr => hocr::render(pages).into_bytes(),
+        ExportFormat::Alto => alto::render(pages).into_bytes(),
+        ExportFormat::Pdf => pdf::render(pages)?,
+    })
+}
+
+/// Write the results of one image, one `Ocr` per page, to `path`
+pub fn export(pages: &[Ocr], format: ExportFormat, path: &Path) -> Result<(), ExportError> {
+    let content = render(pages, format)?;
+    fs::write(path, content)?;
+    Ok(())
+}
+
+/// Recognize the text of every page of `image` and export it to `output`, without the UI
+pub fn export_image(
+    engine: &dyn OcrEngine,
+    options: &OcrOptions,
+    image: &Path,
+    output: &Path,
+    format: ExportFormat,
+) -> Result<(), ExportError> {
+    // Through the pages, so the boxes of a photo match its upright image
+    let path = image.to_string_lossy();
+    let pages = (0..page_count(image))
+        .map(|page| recognize_cached(engine, None, &path, page, options, false))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(ExportError::Ocr)?;
+    export(&pages, format, output)
+}
+
+/// Size of the image the boxes refer to, or the extent of the boxes if the engine did not tell
+fn page_size(ocr: &Ocr) -> Size {
+    if ocr.image_size.width > 0.0 && ocr.image_size.height > 0.0 {
+        return ocr.image_size;
+    }
+    let extent = ocr.content.iter().fold(Rect::ZERO, |rect, text_box| rect.union(text_box.bounding_rect()));
+    Size::new(extent.x1.max(0.0), extent.y1.max(0.0))
+}
+
+/// Escape text for an XML attribute or element
+fn escape_xml(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            // Not allowed in XML 1.0
+            c if (c as u32) < 0x20 && !matches!(c, '\t' | '\n' | '\r') => {},
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ocr::{mock::MockEngine, tests, OcrTextBox};
+
+    /// A word of the shared fixture, with a confidence that shows in the exported scores
+    pub fn word(text: &str, x: f64, y: f64, width: f64, height: f64) -> OcrTextBox {
+        OcrTextBox { confidence: 0.9, ..tests::word(text, x, y, width, height) }
+    }
+
+    #[test]
+    fn guesses_the_format() {
+        assert_eq!(ExportFormat::from_path(Path::new("scan.HOCR")), Some(ExportFormat::Hocr));
+        assert_eq!(ExportFormat::from_path(Path::new("scan.xml")), Some(ExportFormat::Alto));
+        assert_eq!(ExportFormat::from_path(Path::new("scan")), None);
+        assert_eq!("PDF".parse::<ExportFormat>(), Ok(ExportFormat::Pdf));
+        assert!("docx".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn writes_text_in_reading_order() {
+        let ocr = Ocr::new("image.png", vec![word("world", 60.0, 0.0, 50.0, 20.0), word("Hello", 0.0, 0.0, 50.0, 20.0)]);
+        assert_eq!(render(&[ocr], ExportFormat::Text).unwrap(), b"Hello world\n");
+    }
+
+    #[test]
+    fn exports_every_page_of_an_image() {
+        let dir = std::env::temp_dir().join(format!("imagine-export-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (image, output) = (dir.join("scan.tiff"), dir.join("scan.txt"));
+        let mut encoder = tiff::encoder::TiffEncoder::new(fs::File::create(&image).unwrap()).unwrap();
+        encoder.write_image::<tiff::encoder::colortype::Gray8>(70, 70, &[0; 70 * 70]).unwrap();
+        encoder.write_image::<tiff::encoder::colortype::Gray8>(80, 70, &[0; 80 * 70]).unwrap();
+        drop(encoder);
+
+        export_image(&MockEngine, &OcrOptions::default(), &image, &output, ExportFormat::Text).unwrap();
+        let text = fs::read_to_string(&output).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+        assert_eq!(text.split('\x0c').count(), 2, "{}", text);
+    }
+
+    #[test]
+    fn escapes_xml() {
+        assert_eq!(escape_xml("a < b & \"c\"\u{1}"), "a &lt; b &amp; &quot;c&quot;");
+    }
+}
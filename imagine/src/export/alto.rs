@@ -0,0 +1,95 @@
+//! ALTO v4: the XML layout format of libraries and digitization projects
+
+use std::fmt::Write;
+
+use druid::Rect;
+
+use crate::ocr::Ocr;
+
+use super::{escape_xml, page_size};
+
+/// Position and size attributes of an element, in image pixels
+fn position(rect: Rect) -> String {
+    let rect = rect.round();
+    format!("HPOS=\"{}\" VPOS=\"{}\" WIDTH=\"{}\" HEIGHT=\"{}\"", rect.x0, rect.y0, rect.width(), rect.height())
+}
+
+/// ALTO document of `pages`, one `Ocr` per page
+pub fn render(pages: &[Ocr]) -> String {
+    let mut xml = String::new();
+    xml.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str("<alto xmlns=\"http://www.loc.gov/standards/alto/ns-v4#\" xmlns:xsi=\"http://www.w3.org/2001/XMLSchema-instance\" ");
+    xml.push_str("xsi:schemaLocation=\"http://www.loc.gov/standards/alto/ns-v4# http://www.loc.gov/alto/v4/alto-4-2.xsd\">\n");
+    xml.push_str("  <Description>\n    <MeasurementUnit>pixel</MeasurementUnit>\n");
+    if let Some(ocr) = pages.first() {
+        let _ = writeln!(xml, "    <sourceImageInformation>\n      <fileName>{}</fileName>\n    </sourceImageInformation>", escape_xml(&ocr.img_path));
+    }
+    xml.push_str("    <OCRProcessing ID=\"ocr_processing\">\n      <ocrProcessingStep>\n        <processingSoftware>\n");
+    let _ = writeln!(xml, "          <softwareName>imagine</softwareName>\n          <softwareVersion>{}</softwareVersion>", env!("CARGO_PKG_VERSION"));
+    xml.push_str("        </processingSoftware>\n      </ocrProcessingStep>\n    </OCRProcessing>\n  </Description>\n  <Layout>\n");
+
+    for ocr in pages {
+        // Numbered as in the image, whatever pages are missing from the export
+        let page = ocr.page + 1;
+        let size = page_size(ocr).round();
+        let _ = writeln!(xml, "    <Page ID=\"page_{}\" PHYSICAL_IMG_NR=\"{}\" WIDTH=\"{}\" HEIGHT=\"{}\">", page, page, size.width, size.height);
+        let _ = writeln!(xml, "      <PrintSpace {}>", position(size.to_rect()));
+        let (mut line_id, mut word_id) = (0, 0);
+        for (block_id, block) in ocr.blocks().iter().enumerate() {
+            let _ = writeln!(xml, "        <TextBlock ID=\"block_{}_{}\" {}>", page, block_id + 1, position(block.rect));
+            for line in &block.lines {
+                line_id += 1;
+                let _ = writeln!(xml, "          <TextLine ID=\"line_{}_{}\" {}>", page, line_id, position(line.rect));
+                for (i, word) in line.words.iter().enumerate() {
+                    word_id += 1;
+                    if i > 0 {
+                        xml.push_str("            <SP/>\n");
+                    }
+                    let _ = writeln!(
+                        xml,
+                        "            <String ID=\"word_{}_{}\" CONTENT=\"{}\" {} WC=\"{:.2}\"/>",
+                        page,
+                        word_id,
+                        escape_xml(&word.text),
+                        position(word.bounding_rect()),
+                        word.confidence.clamp(0.0, 1.0),
+                    );
+                }
+                xml.push_str("          </TextLine>\n");
+            }
+            xml.push_str("        </TextBlock>\n");
+        }
+        xml.push_str("      </PrintSpace>\n    </Page>\n");
+    }
+    xml.push_str("  </Layout>\n</alto>\n");
+    xml
+}
+
+#[cfg(test)]
+mod tests {
+    use druid::Size;
+
+    use super::*;
+    use crate::export::tests::word;
+
+    #[test]
+    fn describes_strings_with_their_position() {
+        let ocr = Ocr::new("scan.png", vec![word("\"quoted\"", 10.0, 20.0, 30.0, 10.0), word("next", 45.0, 20.0, 20.0, 10.0)])
+            .with_image_size(Size::new(100.0, 50.0));
+        let xml = render(&[ocr]);
+        assert!(xml.contains("<Page ID=\"page_1\" PHYSICAL_IMG_NR=\"1\" WIDTH=\"100\" HEIGHT=\"50\">"));
+        assert!(xml.contains("<TextLine ID=\"line_1_1\" HPOS=\"10\" VPOS=\"20\" WIDTH=\"55\" HEIGHT=\"10\">"));
+        assert!(xml.contains("<String ID=\"word_1_1\" CONTENT=\"&quot;quoted&quot;\" HPOS=\"10\" VPOS=\"20\" WIDTH=\"30\" HEIGHT=\"10\" WC=\"0.90\"/>"));
+        assert_eq!(xml.matches("<SP/>").count(), 1);
+    }
+
+    #[test]
+    fn numbers_the_pages_as_in_the_image() {
+        let pages = [0, 2].map(|page| Ocr { page, ..Ocr::new("scan.tif", vec![word("text", 0.0, 0.0, 10.0, 10.0)]) });
+        let xml = render(&pages);
+        assert!(xml.contains("<Page ID=\"page_1\" PHYSICAL_IMG_NR=\"1\" WIDTH=\"10\" HEIGHT=\"10\">"));
+        assert!(xml.contains("<Page ID=\"page_3\" PHYSICAL_IMG_NR=\"3\" WIDTH=\"10\" HEIGHT=\"10\">"));
+        assert!(xml.contains("<String ID=\"word_3_1\""));
+        assert!(!xml.contains("page_2"));
+    }
+}
@@ -0,0 +1,101 @@
+//! hOCR: XHTML whose elements carry the bounding boxes in their `title`
+
+use std::fmt::Write;
+
+use druid::Rect;
+
+use crate::ocr::{layout::ReadingDirection, Ocr};
+
+use super::{escape_xml, page_size};
+
+/// `bbox x0 y0 x1 y1` property of an element, in image pixels
+fn bbox(rect: Rect) -> String {
+    let rect = rect.round();
+    format!("bbox {} {} {} {}", rect.x0, rect.y0, rect.x1, rect.y1)
+}
+
+/// hOCR document of `pages`, one `Ocr` per page
+pub fn render(pages: &[Ocr]) -> String {
+    let engines = pages.iter().map(|ocr| ocr.engine.as_str()).find(|engine| !engine.is_empty()).unwrap_or("unknown");
+    let mut html = String::new();
+    html.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    html.push_str("<!DOCTYPE html PUBLIC \"-//W3C//DTD XHTML 1.0 Transitional//EN\" \"http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd\">\n");
+    html.push_str("<html xmlns=\"http://www.w3.org/1999/xhtml\">\n <head>\n  <title></title>\n");
+    html.push_str("  <meta http-equiv=\"Content-Type\" content=\"text/html;charset=utf-8\"/>\n");
+    let _ = writeln!(html, "  <meta name=\"ocr-system\" content=\"imagine {} ({})\"/>", env!("CARGO_PKG_VERSION"), escape_xml(engines));
+    html.push_str("  <meta name=\"ocr-capabilities\" content=\"ocr_page ocr_carea ocr_par ocr_line ocrx_word ocrp_wconf\"/>\n");
+    html.push_str(" </head>\n <body>\n");
+
+    for ocr in pages {
+        // Pages that are not recognized yet are left out, the numbers stay those of the image
+        let page = ocr.page + 1;
+        let dir = match ocr.reading_direction() {
+            ReadingDirection::LeftToRight => "ltr",
+            ReadingDirection::RightToLeft => "rtl",
+        };
+        let _ = writeln!(
+            html,
+            "  <div class=\"ocr_page\" id=\"page_{}\" title=\"image &quot;{}&quot;; {}; ppageno {}\">",
+            page,
+            escape_xml(&ocr.img_path),
+            bbox(page_size(ocr).to_rect()),
+            ocr.page,
+        );
+        let (mut line_id, mut word_id) = (0, 0);
+        for (block_id, block) in ocr.blocks().iter().enumerate() {
+            let block_id = block_id + 1;
+            let _ = writeln!(html, "   <div class=\"ocr_carea\" id=\"block_{}_{}\" title=\"{}\">", page, block_id, bbox(block.rect));
+            let _ = writeln!(html, "    <p class=\"ocr_par\" id=\"par_{}_{}\" dir=\"{}\" title=\"{}\">", page, block_id, dir, bbox(block.rect));
+            for line in &block.lines {
+                line_id += 1;
+                let _ = writeln!(html, "     <span class=\"ocr_line\" id=\"line_{}_{}\" title=\"{}\">", page, line_id, bbox(line.rect));
+                for word in &line.words {
+                    word_id += 1;
+                    let _ = writeln!(
+                        html,
+                        "      <span class=\"ocrx_word\" id=\"word_{}_{}\" title=\"{}; x_wconf {:.0}\">{}</span>",
+                        page,
+                        word_id,
+                        bbox(word.bounding_rect()),
+                        word.confidence * 100.0,
+                        escape_xml(&word.text),
+                    );
+                }
+                html.push_str("     </span>\n");
+            }
+            html.push_str("    </p>\n   </div>\n");
+        }
+        html.push_str("  </div>\n");
+    }
+    html.push_str(" </body>\n</html>\n");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use druid::Size;
+
+    use super::*;
+    use crate::export::tests::word;
+
+    #[test]
+    fn nests_words_in_lines_and_blocks() {
+        let ocr = Ocr::new("a&b.png", vec![word("x<y", 10.0, 20.0, 30.0, 10.0), word("z", 50.0, 20.0, 10.0, 10.0)])
+            .with_image_size(Size::new(100.0, 50.0));
+        let html = render(&[ocr]);
+        assert!(html.contains("title=\"image &quot;a&amp;b.png&quot;; bbox 0 0 100 50; ppageno 0\""));
+        assert!(html.contains("<span class=\"ocr_line\" id=\"line_1_1\" title=\"bbox 10 20 60 30\">"));
+        assert!(html.contains("<span class=\"ocrx_word\" id=\"word_1_1\" title=\"bbox 10 20 40 30; x_wconf 90\">x&lt;y</span>"));
+        assert_eq!(html.matches("class=\"ocrx_word\"").count(), 2);
+    }
+
+    #[test]
+    fn numbers_the_pages_as_in_the_image() {
+        let pages = [0, 2].map(|page| Ocr { page, ..Ocr::new("scan.tif", vec![word("text", 0.0, 0.0, 10.0, 10.0)]) });
+        let html = render(&pages);
+        assert!(html.contains("<div class=\"ocr_page\" id=\"page_1\" title=\"image &quot;scan.tif&quot;; bbox 0 0 10 10; ppageno 0\">"));
+        assert!(html.contains("<div class=\"ocr_page\" id=\"page_3\" title=\"image &quot;scan.tif&quot;; bbox 0 0 10 10; ppageno 2\">"));
+        assert!(html.contains("id=\"word_3_1\""));
+        assert!(!html.contains("page_2"));
+    }
+}
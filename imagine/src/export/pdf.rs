@@ -0,0 +1,230 @@
+//! Searchable PDF: the image with an invisible text layer on top
+//!
+//! Each page is the size of its image, one point per pixel. The image is
+//! embedded as a JPEG and every box gets its text drawn with the invisible
+//! rendering mode, in the standard Helvetica font, scaled and sheared to fill
+//! the box polygon so viewers highlight the text where it appears.
+
+use std::{io::Write, path::Path};
+
+use ::image::{codecs::jpeg::JpegEncoder, ColorType};
+use druid::{Affine, Size};
+
+use crate::{ocr::Ocr, pages::open_page};
+
+use super::ExportError;
+
+/// Quality of the embedded JPEG images
+const JPEG_QUALITY: u8 = 90;
+/// Part of the box height used by the glyphs
+const FONT_SIZE_RATIO: f64 = 0.8;
+/// Position of the baseline from the top of the box, in box heights
+const BASELINE: f64 = 0.8;
+
+/// Widths of the printable ASCII characters in Helvetica, in thousandths of the font size
+const HELVETICA_WIDTHS: [u16; 95] = [
+    278, 278, 355, 556, 556, 889, 667, 191, 333, 333, 389, 584, 278, 333, 278, 278, // space to /
+    556, 556, 556, 556, 556, 556, 556, 556, 556, 556, 278, 278, 584, 584, 584, 556, // 0 to ?
+    1015, 667, 667, 722, 722, 667, 611, 778, 722, 278, 500, 667, 556, 833, 722, 778, // @ to O
+    667, 778, 722, 667, 611, 722, 667, 944, 667, 667, 611, 278, 278, 278, 469, 556, // P to _
+    333, 556, 556, 500, 556, 556, 278, 556, 556, 222, 222, 500, 222, 833, 556, 556, // ` to o
+    556, 556, 333, 500, 278, 556, 500, 722, 500, 500, 500, 334, 260, 334, 584, // p to ~
+];
+/// Width assumed for the other characters
+const DEFAULT_WIDTH: u16 = 556;
+
+/// Byte of a character in the WinAnsi encoding of the font, `?` if it has none
+fn win_ansi(c: char) -> u8 {
+    match c {
+        ' '..='~' | '\u{a0}'..='\u{ff}' => c as u8,
+        '€' => 0x80,
+        '‚' => 0x82,
+        '„' => 0x84,
+        '…' => 0x85,
+        'Œ' => 0x8c,
+        '‘' => 0x91,
+        '’' => 0x92,
+        '“' => 0x93,
+        '”' => 0x94,
+        '•' => 0x95,
+        '–' => 0x96,
+        '—' => 0x97,
+        'œ' => 0x9c,
+        _ => b'?',
+    }
+}
+
+/// Width of encoded text at a font size of 1
+fn text_width(bytes: &[u8]) -> f64 {
+    let width = bytes
+        .iter()
+        .map(|byte| match byte {
+            b' '..=b'~' => HELVETICA_WIDTHS[(byte - b' ') as usize],
+            _ => DEFAULT_WIDTH,
+        })
+        .map(f64::from)
+        .sum::<f64>();
+    width / 1000.0
+}
+
+/// Content stream of a page: the image, then the invisible text
+fn page_content(ocr: &Ocr, page: Size, image_size: Size) -> String {
+    let mut content = format!("q {} 0 0 {} 0 0 cm /Im0 Do Q\nBT 3 Tr\n", page.width, page.height);
+    // From image to page coordinates, the y axis of PDF points up
+    let to_page = Affine::new([1.0, 0.0, 0.0, -1.0, 0.0, page.height])
+        * Affine::scale_non_uniform(page.width / image_size.width, page.height / image_size.height);
+    for text_box in &ocr.content {
+        let text = text_box.text.trim().chars().map(win_ansi).collect::<Vec<_>>();
+        let (transform, size) = text_box.transform();
+        let font_size = size.height * FONT_SIZE_RATIO;
+        let width = text_width(&text) * font_size;
+        if text.is_empty() || width < f64::EPSILON || font_size < f64::EPSILON {
+            continue;
+        }
+        // From text space, with the y axis up from the baseline, to the box
+        let to_box = Affine::new([size.width / width, 0.0, 0.0, -1.0, 0.0, size.height * BASELINE]);
+        let [a, b, c, d, e, f] = (to_page * transform * to_box).as_coeffs();
+        let hex = text.iter().map(|byte| format!("{:02X}", byte)).collect::<String>();
+        content.push_str(&format!(
+            "/F0 {:.2} Tf {:.4} {:.4} {:.4} {:.4} {:.2} {:.2} Tm <{}> Tj\n",
+            font_size, a, b, c, d, e, f, hex
+        ));
+    }
+    content.push_str("ET\n");
+    content
+}
+
+/// Writes numbered objects and keeps their offsets for the cross-reference table
+struct PdfWriter {
+    buffer: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl PdfWriter {
+    fn new() -> Self {
+        Self { buffer: b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec(), offsets: Vec::new() }
+    }
+
+    /// Write the next object, which must be numbered `offsets.len() + 1`
+    fn object(&mut self, id: usize, dictionary: &str) {
+        debug_assert_eq!(id, self.offsets.len() + 1);
+        self.offsets.push(self.buffer.len());
+        let _ = write!(self.buffer, "{} 0 obj\n{}\nendobj\n", id, dictionary);
+    }
+
+    fn stream(&mut self, id: usize, dictionary: &str, data: &[u8]) {
+        debug_assert_eq!(id, self.offsets.len() + 1);
+        self.offsets.push(self.buffer.len());
+        let _ = write!(self.buffer, "{} 0 obj\n<< {} /Length {} >>\nstream\n", id, dictionary, data.len());
+        self.buffer.extend_from_slice(data);
+        self.buffer.extend_from_slice(b"\nendstream\nendobj\n");
+    }
+
+    fn finish(mut self, root: usize) -> Vec<u8> {
+        let xref = self.buffer.len();
+        let _ = write!(self.buffer, "xref\n0 {}\n0000000000 65535 f \n", self.offsets.len() + 1);
+        for offset in &self.offsets {
+            let _ = writeln!(self.buffer, "{:010} 00000 n ", offset);
+        }
+        let _ = write!(
+            self.buffer,
+            "trailer\n<< /Size {} /Root {} 0 R >>\nstartxref\n{}\n%%EOF\n",
+            self.offsets.len() + 1,
+            root,
+            xref
+        );
+        self.buffer
+    }
+}
+
+/// PDF of `pages`, one `Ocr` per page, with the images read from `Ocr::img_path` and `Ocr::page`
+pub fn render(pages: &[Ocr]) -> Result<Vec<u8>, ExportError> {
+    // Objects 1 to 3 are the catalog, the page tree and the font, then each page has three objects
+    let page_id = |page: usize| 4 + 3 * page;
+    let mut pdf = PdfWriter::new();
+    let kids = (0..pages.len()).map(|page| format!("{} 0 R", page_id(page))).collect::<Vec<_>>().join(" ");
+    pdf.object(1, "<< /Type /Catalog /Pages 2 0 R >>");
+    pdf.object(2, &format!("<< /Type /Pages /Kids [{}] /Count {} >>", kids, pages.len()));
+    pdf.object(3, "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>");
+
+    for (page, ocr) in pages.iter().enumerate() {
+        let image = open_page(Path::new(&ocr.img_path), ocr.page).map_err(|e| ExportError::Image(e.to_string()))?.to_rgb8();
+        let (width, height) = image.dimensions();
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, JPEG_QUALITY)
+            .encode(image.as_raw(), width, height, ColorType::Rgb8)
+            .map_err(|e| ExportError::Image(e.to_string()))?;
+
+        let media_size = Size::new(width as f64, height as f64);
+        // The boxes of an engine that did not give the image size are in its pixels
+        let boxes_size = match ocr.image_size {
+            size if size.width > 0.0 && size.height > 0.0 => size,
+            _ => media_size,
+        };
+        let id = page_id(page);
+        pdf.object(id, &format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F0 3 0 R >> /XObject << /Im0 {} 0 R >> >> /Contents {} 0 R >>",
+            width, height, id + 2, id + 1
+        ));
+        pdf.stream(id + 1, "", page_content(ocr, media_size, boxes_size).as_bytes());
+        pdf.stream(
+            id + 2,
+            &format!("/Type /XObject /Subtype /Image /Width {} /Height {} /ColorSpace /DeviceRGB /BitsPerComponent 8 /Filter /DCTDecode", width, height),
+            &jpeg,
+        );
+    }
+    Ok(pdf.finish(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use druid::Point;
+
+    use super::*;
+    use crate::export::tests::word;
+
+    #[test]
+    fn encodes_text_in_win_ansi() {
+        let text = "Été – 5€ 日".chars().map(win_ansi).collect::<Vec<_>>();
+        assert_eq!(text, vec![0xc9, b't', 0xe9, b' ', 0x96, b' ', b'5', 0x80, b' ', b'?']);
+        assert!((text_width(b"Hi") - 0.944).abs() < 1e-9);
+    }
+
+    #[test]
+    fn stretches_the_text_over_its_box() {
+        let ocr = Ocr::new("scan.png", vec![word("Hi", 10.0, 20.0, 50.0, 10.0)]);
+        let content = page_content(&ocr, Size::new(100.0, 50.0), Size::new(100.0, 50.0));
+        let line = content.lines().find(|line| line.ends_with("Tj")).unwrap();
+        let values = line.split_whitespace().collect::<Vec<_>>();
+        assert_eq!(values[..3], ["/F0", "8.00", "Tf"]);
+        assert_eq!(values[9..], ["Tm", "<4869>", "Tj"]);
+        // The baseline starts at the bottom left of the glyphs, 8 pixels under the top of the box
+        let coeffs = values[3..9].iter().map(|value| value.parse::<f64>().unwrap()).collect::<Vec<_>>();
+        let to_page = Affine::new(coeffs.try_into().unwrap());
+        assert!((to_page * Point::ORIGIN - Point::new(10.0, 22.0)).hypot() < 1e-2);
+        assert!((to_page * Point::new(0.944 * 8.0, 0.0) - Point::new(60.0, 22.0)).hypot() < 1e-2);
+    }
+
+    #[test]
+    fn writes_a_consistent_cross_reference_table() {
+        let dir = std::env::temp_dir().join(format!("imagine-pdf-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let image = dir.join("scan.png");
+        ::image::RgbImage::new(40, 20).save(&image).unwrap();
+        let ocr = Ocr::new(image.to_string_lossy(), vec![word("Hello", 2.0, 2.0, 30.0, 10.0)]);
+        let pdf = render(&[ocr]).unwrap();
+        fs::remove_dir_all(dir).unwrap();
+
+        let text = String::from_utf8_lossy(&pdf);
+        let startxref = text.rsplit("startxref\n").next().unwrap().lines().next().unwrap().parse::<usize>().unwrap();
+        assert!(pdf[startxref..].starts_with(b"xref\n0 7\n"));
+        let entries = text[text.find("0000000000 65535 f \n").unwrap()..].lines().skip(1).take(6).collect::<Vec<_>>();
+        for (id, entry) in entries.iter().enumerate() {
+            let offset = entry[..10].parse::<usize>().unwrap();
+            assert!(pdf[offset..].starts_with(format!("{} 0 obj", id + 1).as_bytes()));
+        }
+        assert!(text.contains("/MediaBox [0 0 40 20]"));
+    }
+}
@@ -1,7 +1,4 @@
-use std::path::Path;
-
-use druid::{piet::{InterpolationMode, Text}, LifeCycleCtx, LifeCycle, widget::{Axis, TextBox, SizedBox, Padding, BackgroundBrush}, Affine, Target, Rect, im::Vector, LensExt};
-use ::image::{open, ImageError};
+use druid::{piet::InterpolationMode, LifeCycleCtx, LifeCycle, widget::Axis, Affine, MouseButton, MouseEvent, Rect};
 
 #[cfg(target_os = "macos")]
 use druid::piet::CoreGraphicsImage as CoreGraphicsImage;
@@ -11,29 +8,84 @@ use druid::piet::CairoImage as CoreGraphicsImage;
 
 use crate::prelude::*;
 
-use self::{delegate::{CTRL, SEND_OCR, RESET_OCR}, ocr::Ocr};
+use self::{animation::Animation, delegate::{CENTER_IMAGE, CTRL, FOCUS_IMAGE, OCR_REGION}, loader::{DecodedImage, LoadError}, metadata::Metadata, ocr::Ocr, pages::Document, overlay::OcrOverlay, search::SearchState};
+
+
 
+/// Smallest area, in square view pixels, of a dragged region sent to the OCR
+const MIN_REGION_AREA: f64 = 16.0;
 
+/// How the zoom is chosen when an image is opened
+#[derive(Debug, Clone, Copy, PartialEq, Data, Default)]
+pub enum ZoomMode {
+    /// Shrink large images to fit the window, small ones keep their actual size
+    #[default]
+    ShrinkToFit,
+    /// Fit the image to the window, enlarging small images
+    Fit,
+    /// A fixed zoom factor
+    Factor(f64),
+}
+
+/// Whether the image of the state is the one that was asked for
+#[derive(Debug, Clone, PartialEq, Data, Default)]
+pub enum ImageStatus {
+    /// The image is shown
+    #[default]
+    Ready,
+    /// The image at this path is being decoded, the previous one is still shown
+    Loading(String),
+    /// The image at `path` could not be opened, nothing is shown
+    Error(LoadError),
+}
 
 #[derive(Clone, Data, Lens)]
 pub struct ImageState {
     pub zoom: f64,
+    pub zoom_mode: ZoomMode,
     pub min_zoom: f64,
     pub center: Vec2,
+    /// The image, or the current frame of the animation or page of the document
     pub image_buf: Arc<ImageBuf>,
+    pub animation: Option<Animation>,
+    pub document: Option<Document>,
+    /// EXIF metadata of the image, listed in the info panel
+    pub metadata: Metadata,
     pub mouse_pos: Vec2,
     pub path: String,
-    //pub recognized_list: Vector<String>
+    pub status: ImageStatus,
+    /// Text recognized in the current image, once the OCR is done
+    pub ocr: Option<Ocr>,
+    /// Why the last background task on the image failed, shown over the view until dismissed
+    pub task_error: Option<String>,
+    pub search: SearchState,
+    /// Boxes with a lower OCR confidence are hidden
+    pub min_confidence: f64,
+    /// Outline the boxes with a color showing their confidence
+    pub show_outlines: bool,
+    /// Show the info panel next to the image
+    pub show_info: bool,
 }
 
 impl Default for ImageState {
     fn default() -> Self {
         Self {
             zoom: 1.0,
+            zoom_mode: ZoomMode::default(),
             center: Vec2::new(0.0, 0.0),
             mouse_pos: Vec2::new(0.0, 0.0),
             image_buf: Arc::new(ImageBuf::empty()),
+            animation: None,
+            document: None,
+            metadata: Metadata::default(),
             path: String::new(),
+            status: ImageStatus::default(),
+            ocr: None,
+            task_error: None,
+            search: SearchState::default(),
+            min_confidence: 0.0,
+            show_outlines: true,
+            show_info: false,
             min_zoom: 0.2,
         }
     }
@@ -41,8 +93,12 @@ impl Default for ImageState {
 
 pub trait ImageStateTrait {
     fn add_zoom(&mut self, zoom_delta: f64, ctx: &mut EventCtx);
-    fn change_image(&mut self, path: &str, window_size: Size, handle: druid::ExtEventSink);
+    fn change_image(&mut self, path: &str, image: DecodedImage, window_size: Size);
+    fn reset_zoom(&mut self, window_size: Size);
+    fn reload(&mut self, image: DecodedImage);
+    fn show_error(&mut self, path: &str, error: LoadError);
     fn get_rect(&self) -> druid::Rect;
+    fn get_zoom(&self) -> f64;
     fn set_mouse_pos(&mut self, mouse_pos: Vec2);
     fn get_center(&self) -> Vec2;
     fn set_center(&mut self, center: Vec2);
@@ -50,26 +106,80 @@ pub trait ImageStateTrait {
 
 
 impl ImageStateTrait for ImageState {
-    /// Change the image and reset the zoom
-    fn change_image(&mut self, path: &str, window_size: Size, handle: druid::ExtEventSink) {
-        self.image_buf = Arc::new(load_and_convert_image(path));
+    /// Change the image, reset the zoom and drop the text of the previous image
+    fn change_image(&mut self, path: &str, image: DecodedImage, window_size: Size) {
+        self.image_buf = image.image_buf;
+        self.animation = image.animation;
+        self.document = image.document;
+        self.metadata = image.metadata;
         self.path = path.to_string();
-        
+        self.status = ImageStatus::Ready;
+        self.ocr = None;
+        self.task_error = None;
+        self.search.refresh(None);
+        self.reset_zoom(window_size);
+    }
+
+    /// Replace the image after it changed on disk, keeping the zoom and the
+    /// center where possible
+    fn reload(&mut self, image: DecodedImage) {
+        let (old_size, new_size) = (self.image_buf.size(), image.image_buf.size());
+        if old_size != new_size && old_size.width > 0.0 && old_size.height > 0.0 {
+            // Keep the same relative position in the resized image
+            self.center = Vec2::new(
+                self.center.x * new_size.width / old_size.width,
+                self.center.y * new_size.height / old_size.height,
+            );
+        }
+        self.animation = match (image.animation, &self.animation) {
+            (Some(animation), Some(previous)) => Some(animation.continue_from(previous)),
+            (animation, _) => animation,
+        };
+        self.document = match (image.document, &self.document) {
+            (Some(document), Some(previous)) => Some(document.continue_from(previous)),
+            (document, _) => document,
+        };
+        self.image_buf = match (&self.animation, &self.document) {
+            (Some(animation), _) => animation.frame().image_buf.clone(),
+            (None, Some(document)) => document.pages[document.current].clone(),
+            (None, None) => image.image_buf,
+        };
+        self.metadata = image.metadata;
+        self.ocr = None;
+        self.search.refresh(None);
+    }
+
+    /// Drop the previous image and its text, the error is shown instead
+    fn show_error(&mut self, path: &str, error: LoadError) {
+        self.image_buf = Arc::new(ImageBuf::empty());
+        self.animation = None;
+        self.document = None;
+        self.metadata = Metadata::default();
+        self.path = path.to_string();
+        self.status = ImageStatus::Error(error);
+        self.ocr = None;
+        self.task_error = None;
+        self.search.refresh(None);
+    }
+
+    /// Zoom as the zoom mode asks for a window of `window_size` and center the image
+    fn reset_zoom(&mut self, window_size: Size) {
         let image_rect = self.image_buf.size().to_rect();
+        if image_rect.area() == 0.0 || window_size.area() == 0.0 {
+            // Nothing to fit, keep the current zoom
+            return;
+        }
         // Compute zoom to fit image in window
         let zoom_x = window_size.width / image_rect.width();
         let zoom_y = window_size.height / image_rect.height();
-        self.zoom = zoom_x.min(zoom_y);
-        self.center = image_rect.center().to_vec2();
-        self.min_zoom = self.zoom / 5.0;
-        
-        // Call asynchroneously the ocr
-        let path = path.to_string();
-
-        std::thread::spawn(move || {
-            let ocr = Ocr::get_text(path);
-            handle.submit_command(SEND_OCR, ocr, Target::Auto).expect("Failed to send OCR complete event");
-        });
+        let fit = zoom_x.min(zoom_y);
+        self.zoom = match self.zoom_mode {
+            ZoomMode::ShrinkToFit => fit.min(1.0),
+            ZoomMode::Fit => fit,
+            ZoomMode::Factor(factor) => factor,
+        };
+        self.center = image_rect.center().to_vec2() * self.zoom;
+        self.min_zoom = self.zoom.min(fit) / 5.0;
     }
 
     /// Get the rect of the image in the window (with the current zoom)
@@ -77,11 +187,15 @@ impl ImageStateTrait for ImageState {
         self.image_buf.size().to_rect().scale_from_origin(self.zoom)
     }
 
+    fn get_zoom(&self) -> f64 {
+        self.zoom
+    }
+
     /// Add a zoom delta to the current zoom
     /// Zoom is clamped between min_zoom and infinity
     /// Zoom is centered on the mouse position
     fn add_zoom(&mut self, zoom_delta: f64, ctx: &mut EventCtx) {
-        if self.zoom + zoom_delta < self.min_zoom {
+        if self.image_buf.size().area() == 0.0 || self.zoom + zoom_delta < self.min_zoom {
             return;
         }
         self.zoom += zoom_delta;
@@ -118,39 +232,20 @@ impl ImageStateTrait for ImageState {
     }
 }
 
-type TextField = String;
-
-#[derive(Clone, Data, Lens)]
-struct OcrText {
-    text_fields: Vector<TextField>,
-}
+#[derive(Default)]
 pub struct ImageWidget {
     cached_image: Option<CoreGraphicsImage>,
-    text_boxes: Vec<Container<String>>,
-}
-
-impl Default for ImageWidget {
-    fn default() -> Self {
-        let text_box = TextBox::<String>::new().with_text_color(Color::TRANSPARENT)
-        
-        .background(BackgroundBrush::Color(Color::RED))
-        ;
-        Self { 
-            cached_image: None,
-            text_boxes: vec![text_box],
-        }
-    }
+    overlay: OcrOverlay,
 }
 
 impl Widget<ImageState> for ImageWidget {
-    fn lifecycle(&mut self, lc_ctx: &mut LifeCycleCtx, lc: &LifeCycle, data: &ImageState, env: &Env) {
-        for text_box in &mut self.text_boxes {
-            text_box.lifecycle(lc_ctx, lc, &"pomme".to_string(), env);
-        }
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &ImageState, env: &Env) {
+        self.overlay.lifecycle(ctx, event, data, env);
     }
 
-    fn update(&mut self, ctx: &mut UpdateCtx, prev_data: &ImageState, new_data: &ImageState, _: &Env) {
-        if prev_data.path != new_data.path {   
+    fn update(&mut self, ctx: &mut UpdateCtx, prev_data: &ImageState, new_data: &ImageState, env: &Env) {
+        // A reloaded image keeps its path
+        if prev_data.path != new_data.path || !prev_data.image_buf.same(&new_data.image_buf) {
             self.cached_image = None;
             ctx.request_paint();
             ctx.request_layout();
@@ -164,21 +259,28 @@ impl Widget<ImageState> for ImageWidget {
             ctx.request_paint();
             ctx.request_layout();
         }
+
+        if !prev_data.ocr.same(&new_data.ocr) {
+            ctx.request_paint();
+            ctx.request_layout();
+        }
+
+        if !prev_data.search.same(&new_data.search) {
+            ctx.request_paint();
+        }
+        self.overlay.update(ctx, prev_data, new_data, env);
     }
         
-    fn layout(&mut self, lay: &mut LayoutCtx, _bc: &BoxConstraints, data: &ImageState, env: &Env) -> Size {                
-        let image_rect = data.get_rect();
-        // Compute text boxes
-        let mut text_boxes = Vec::new();
-        for text_box in &mut self.text_boxes {
-            let text_box_size = text_box.layout(lay, _bc, &"pomme".to_string(), env);
-            let text_box_rect = Rect::from_points(Point::new(0.0, 0.0), Point::new(20.0, 10.0));
-            text_boxes.push(text_box_rect);
-        }
-        image_rect.size()
+    fn layout(&mut self, ctx: &mut LayoutCtx, _bc: &BoxConstraints, data: &ImageState, env: &Env) -> Size {                
+        let image_size = data.get_rect().size();
+        self.overlay.layout(ctx, &BoxConstraints::tight(image_size), data, env);
+        image_size
     }
     
     fn paint(&mut self, ctx: &mut PaintCtx, data: &ImageState, env: &Env) {
+        if data.image_buf.width() == 0 || data.image_buf.height() == 0 {
+            return;
+        }
         if let Some(cached_img) = self.cached_image.as_ref()  {
             let image_rect = data.get_rect();
             ctx.draw_image(cached_img, image_rect, InterpolationMode::Bilinear);
@@ -191,64 +293,23 @@ impl Widget<ImageState> for ImageWidget {
             self.cached_image = Some(cached_img);
         }
 
-        // Draw text boxes
-        println!("text boxes: {:?}", self.text_boxes.len());
-        for text_box in self.text_boxes.iter_mut() {
-            println!("paint text box");
-            text_box.paint(ctx, &"pomme".to_string(), env);
+        // Highlight the search hits, the selected one stands out
+        for (i, outline) in data.match_outlines().iter().enumerate() {
+            let color = if i == 0 { &colors::SEARCH_CURRENT } else { &colors::SEARCH_MATCH };
+            ctx.fill(outline, color);
         }
 
-        // Draw ocr boxes
-        /*if let Some(ocr) = self.ocr.as_ref() {
-            for ocr_text_box in &ocr.content {
-                // Draw boxe
-                let mut box_rect = Rect::from_points(ocr_text_box.boxes[0], ocr_text_box.boxes[1]);
-                for point in &ocr_text_box.boxes {
-                    box_rect = box_rect.union(Rect::from_center_size(*point, (1.0, 1.0)));
-                }
-                // Adapt rect to zoom
-                box_rect = box_rect.scale_from_origin(data.zoom);
-                ctx.stroke(box_rect, &Color::RED, 1.0);
-            }
-        }*/
+        // Draw the selectable text over the image
+        self.overlay.paint(ctx, data, env);
         
         // TODO: Add margin to image rect
     }
 
-    fn event(&mut self, ctx: &mut EventCtx, event: &Event, _data: &mut ImageState, _env: &Env) {
-        if let Event::Command(cmd) = event {
-            if cmd.is(SEND_OCR) {
-                //self.ocr = Some(cmd.get_unchecked(SEND_OCR).clone());
-
-                ctx.request_paint();    
-            } else if cmd.is(RESET_OCR) {
-                //self.text_boxes.clear();
-            }
-        }
-        for text_box in &mut self.text_boxes {
-            text_box.event(ctx, event, &mut "pomme".to_string(), _env);
-        }
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut ImageState, env: &Env) {
+        self.overlay.event(ctx, event, data, env);
     }
 }
 
-pub fn load_and_convert_image(path: impl AsRef<Path>) -> ImageBuf {
-    let image = match open(path) {
-        Ok(image) => image.to_rgba8(),
-        Err(e) => {
-            match e {
-                ImageError::IoError(e) => eprintln!("Failed to open image: {}", e),
-                ImageError::Decoding(e) => eprintln!("Failed to decode image: {}", e),
-                _ => eprintln!("Failed to open image: {}", e),
-            };
-            return ImageBuf::empty();
-        }
-    };
-
-
-    let size = (image.width() as usize, image.height() as usize);
-    // TODO: Check if the image has the correct format
-    ImageBuf::from_raw(image.into_raw(), druid::piet::ImageFormat::RgbaSeparate, size.0, size.1)
-}
 pub struct ImageView<T, W>
 where
     T: ImageStateTrait,
@@ -256,6 +317,10 @@ where
 {
     inner: Scroll<T, W>,
     ctrl_pressed: bool,
+    /// Corners of the region being dragged with shift, in the view coordinates
+    region_drag: Option<(Point, Point)>,
+    /// Scroll to the center of the image state after the next layout
+    center_pending: bool,
 }
 
 impl<T, W> ImageView<T, W>
@@ -267,10 +332,71 @@ where
         Self {
             inner: Scroll::new(child).horizontal().vertical(),
             ctrl_pressed: false,
+            region_drag: None,
+            center_pending: false,
+        }
+    }
+
+    /// Offset of the image when it is smaller than the viewport and drawn centered
+    fn centering_offset(&self, data: &T) -> Vec2 {
+        let image_rect = data.get_rect();
+        let viewport_rect = self.inner.viewport_rect();
+        Vec2::new(
+            ((viewport_rect.width() - image_rect.width()) / 2.0).max(0.0),
+            ((viewport_rect.height() - image_rect.height()) / 2.0).max(0.0),
+        )
+    }
+
+    /// Convert a point of the view to the coordinates of the unzoomed image
+    fn to_image_point(&self, data: &T, point: Point) -> Point {
+        let point = point - self.centering_offset(data) + self.inner.offset();
+        (point.to_vec2() / data.get_zoom()).to_point()
+    }
+
+    /// Shift+drag selects a region of the image to OCR, returns whether the event was used
+    fn region_event(&mut self, ctx: &mut EventCtx, event: &Event, data: &T) -> bool {
+        match event {
+            Event::MouseDown(mouse_event) if mouse_event.button == MouseButton::Left && mouse_event.mods.shift() => {
+                self.region_drag = Some((mouse_event.pos, mouse_event.pos));
+                ctx.set_active(true);
+            },
+            Event::MouseMove(mouse_event) if self.region_drag.is_some() => {
+                if let Some((_, end)) = &mut self.region_drag {
+                    *end = mouse_event.pos;
+                }
+                ctx.request_paint();
+            },
+            Event::MouseUp(mouse_event) if mouse_event.button == MouseButton::Left && self.region_drag.is_some() => {
+                let (start, end) = self.region_drag.take().unwrap();
+                ctx.set_active(false);
+                ctx.request_paint();
+                if Rect::from_points(start, end).area() >= MIN_REGION_AREA {
+                    let region = Rect::from_points(self.to_image_point(data, start), self.to_image_point(data, end));
+                    ctx.submit_command(OCR_REGION.with(region));
+                }
+            },
+            _ => return false,
         }
+        ctx.set_handled();
+        true
     }
 }
 
+/// Map the position of a mouse event with `transform`, other events are left untouched
+pub fn transform_mouse_event(event: &Event, transform: Affine) -> Event {
+    let map = |mouse_event: &MouseEvent| {
+        let mut mouse_event = mouse_event.clone();
+        mouse_event.pos = transform * mouse_event.pos;
+        mouse_event
+    };
+    match event {
+        Event::MouseDown(mouse_event) => Event::MouseDown(map(mouse_event)),
+        Event::MouseUp(mouse_event) => Event::MouseUp(map(mouse_event)),
+        Event::MouseMove(mouse_event) => Event::MouseMove(map(mouse_event)),
+        Event::Wheel(mouse_event) => Event::Wheel(map(mouse_event)),
+        event => event.clone(),
+    }
+}
 
 impl<T, W> Widget<T> for ImageView<T, W>
 where
@@ -278,23 +404,36 @@ where
     W: Widget<T>,
 {
     fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut T, env: &Env) {
+        // The keyboard events only reach the focused widget and its ancestors
+        match event {
+            Event::WindowConnected | Event::MouseDown(_) => ctx.request_focus(),
+            Event::Command(cmd) if cmd.is(FOCUS_IMAGE) => ctx.request_focus(),
+            _ => {},
+        }
+        if self.region_event(ctx, event, data) {
+            return;
+        }
+        if let Event::Command(cmd) = event {
+            if cmd.is(CENTER_IMAGE) {
+                // The zoom may have changed: scroll once the image has its new size
+                self.center_pending = true;
+                ctx.request_layout();
+                return;
+            }
+        }
         let mut zoomed = false;
         match event {
             Event::Zoom(zoom_delta) => {
                 data.add_zoom(*zoom_delta, ctx);
                 zoomed = true;
             },            
-            Event::Command(cmd) => {
-                if cmd.is(CTRL) {
-                    self.ctrl_pressed = *cmd.get_unchecked(CTRL);
-                }
+            Event::Command(cmd) if cmd.is(CTRL) => {
+                self.ctrl_pressed = *cmd.get_unchecked(CTRL);
             },
-            Event::Wheel(wheel_event) => {
-                if self.ctrl_pressed {
-                    let zoom_delta = -wheel_event.wheel_delta.y * 0.001;
-                    data.add_zoom(zoom_delta, ctx);
-                    zoomed = true;
-                }
+            Event::Wheel(wheel_event) if self.ctrl_pressed => {
+                let zoom_delta = -wheel_event.wheel_delta.y * 0.001;
+                data.add_zoom(zoom_delta, ctx);
+                zoomed = true;
             },
             Event::MouseMove(mouse_event) => {
                 data.set_mouse_pos(mouse_event.pos.to_vec2());
@@ -311,7 +450,9 @@ where
             self.inner.scroll_to_on_axis(ctx, Axis::Vertical, scroll_to.y);
             
         } else {
-            self.inner.event(ctx, event, data, env);
+            // Move the mouse events to the coordinates of the centered image
+            let event = transform_mouse_event(event, Affine::translate(-self.centering_offset(data)));
+            self.inner.event(ctx, &event, data, env);
         }
         // Update the center of the image if the scroll position changed
         let scroll_pos = self.inner.offset();
@@ -321,6 +462,9 @@ where
     }
 
     fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, data: &T, env: &Env) {
+        if let LifeCycle::WidgetAdded = event {
+            ctx.register_for_focus();
+        }
         self.inner.lifecycle(ctx, event, data, env);
     }
 
@@ -343,26 +487,30 @@ where
     }
 
     fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, data: &T, env: &Env) -> Size {
-        self.inner.layout(ctx, bc, data, env)
+        let size = self.inner.layout(ctx, bc, data, env);
+        if self.center_pending {
+            self.center_pending = false;
+            let scroll_to = data.get_center() - size.to_vec2() / 2.0;
+            self.inner.scroll_to_on_axis(ctx, Axis::Horizontal, scroll_to.x);
+            self.inner.scroll_to_on_axis(ctx, Axis::Vertical, scroll_to.y);
+        }
+        size
     }
 
     fn paint(&mut self, ctx: &mut PaintCtx, data: &T, env: &Env) {
         // Draw inner widget centered in the viewport
-        let image_rect = data.get_rect();
-        let viewport_rect = self.inner.viewport_rect();
+        let offset = self.centering_offset(data);
         
         ctx.with_save(|ctx| {
             // Center the non overflowing axis
-            if image_rect.width() < viewport_rect.width() {
-                let offset = (viewport_rect.width() - image_rect.width()) / 2.0;
-                ctx.transform(Affine::translate((offset, 0.0)));
-            }
-
-            if image_rect.height() < viewport_rect.height() {
-                let offset = (viewport_rect.height() - image_rect.height()) / 2.0;
-                ctx.transform(Affine::translate((0.0, offset)));
-            }
+            ctx.transform(Affine::translate(offset));
             self.inner.paint(ctx, data, env);
         });
+
+        if let Some((start, end)) = self.region_drag {
+            let region = Rect::from_points(start, end);
+            ctx.fill(region, &colors::REGION_FILL);
+            ctx.stroke(region, &colors::REGION_BORDER, 1.0);
+        }
     }
 }
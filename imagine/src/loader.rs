@@ -0,0 +1,255 @@
+//! Decoding of the images on a background thread
+//!
+//! Large images take seconds to decode, so [`ImageLoader`] decodes them away
+//! from the UI thread and sends the result back with [`IMAGE_LOADED`]. The
+//! previous image stays on screen in the meantime.
+
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+    sync::{atomic::{AtomicU64, Ordering}, mpsc::{self, Receiver, Sender}, Arc},
+};
+
+use druid::{Data, ExtEventSink, ImageBuf, Target};
+use ::image::{io::Reader, ImageError};
+
+use crate::{animation::{self, Animation}, delegate::IMAGE_LOADED, metadata::{self, Metadata}, pages::{self, Document}};
+
+/// Largest number of pixels of an image, 1 GiB once decoded to RGBA
+pub const MAX_PIXELS: u64 = 1 << 28;
+
+/// Reason an image could not be opened
+#[derive(Debug, Clone, PartialEq, Data)]
+pub enum LoadError {
+    NotFound,
+    PermissionDenied,
+    /// The format of the file, or `None` if it was not recognized
+    Unsupported(Option<String>),
+    /// The file is truncated or its data is invalid
+    Corrupt(String),
+    TooLarge { width: u32, height: u32 },
+    /// The path is not valid UTF-8, the viewer keeps the paths as text
+    InvalidPath,
+    /// Another error while reading the file
+    Io(String),
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::NotFound => write!(f, "the file does not exist"),
+            LoadError::PermissionDenied => write!(f, "permission denied"),
+            LoadError::Unsupported(Some(format)) => write!(f, "unsupported image format: {}", format),
+            LoadError::Unsupported(None) => write!(f, "the file is not an image in a known format"),
+            LoadError::Corrupt(e) => write!(f, "the image data is corrupt: {}", e),
+            LoadError::TooLarge { width, height } => write!(f, "the image is too large: {}×{} pixels", width, height),
+            LoadError::InvalidPath => write!(f, "the file name is not valid UTF-8"),
+            LoadError::Io(e) => write!(f, "failed to read the file: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+impl From<io::Error> for LoadError {
+    fn from(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::NotFound => LoadError::NotFound,
+            io::ErrorKind::PermissionDenied => LoadError::PermissionDenied,
+            io::ErrorKind::UnexpectedEof => LoadError::Corrupt("the file is truncated".to_string()),
+            _ => LoadError::Io(e.to_string()),
+        }
+    }
+}
+
+impl From<ImageError> for LoadError {
+    fn from(e: ImageError) -> Self {
+        match e {
+            ImageError::IoError(e) => e.into(),
+            ImageError::Unsupported(e) => LoadError::Unsupported(Some(e.to_string())),
+            e => LoadError::Corrupt(e.to_string()),
+        }
+    }
+}
+
+/// A decoding request, tagged with the generation it was submitted in
+struct LoadJob {
+    generation: u64,
+    path: String,
+    reload: bool,
+    target: Target,
+    handle: ExtEventSink,
+}
+
+/// A decoded image, never empty
+#[derive(Clone)]
+pub struct DecodedImage {
+    /// The image, or the first frame of an animation or page of a document
+    pub image_buf: Arc<ImageBuf>,
+    /// Every frame, if the image is animated
+    pub animation: Option<Animation>,
+    /// Every page, if the image has several pages
+    pub document: Option<Document>,
+    pub metadata: Metadata,
+}
+
+/// Decoded image, or the reason it could not be decoded, sent to the UI with [`IMAGE_LOADED`]
+#[derive(Clone)]
+pub struct LoadedImage {
+    pub path: String,
+    pub result: Result<DecodedImage, LoadError>,
+    /// The image changed on disk and replaces the same one, the zoom is kept
+    pub reload: bool,
+}
+
+/// Decodes the images one at a time on a background thread
+///
+/// Like the OCR jobs, every request supersedes the previous ones: requests
+/// still in the queue are dropped, and the image being decoded when a newer
+/// request arrived is discarded instead of being sent to the UI.
+pub struct ImageLoader {
+    generation: Arc<AtomicU64>,
+    sender: Sender<LoadJob>,
+}
+
+impl Default for ImageLoader {
+    fn default() -> Self {
+        let generation = Arc::new(AtomicU64::new(0));
+        let (sender, receiver) = mpsc::channel();
+        let worker_generation = generation.clone();
+        std::thread::Builder::new()
+            .name("image-loader".to_string())
+            .spawn(move || run_jobs(worker_generation, receiver))
+            .expect("Failed to spawn the image loader thread");
+        Self { generation, sender }
+    }
+}
+
+impl ImageLoader {
+    /// Decode the image at `path`, the result is sent to `target`
+    pub fn load(&self, path: impl Into<String>, target: Target, handle: ExtEventSink) {
+        self.queue(path.into(), false, target, handle)
+    }
+
+    /// Decode the image at `path` again after it changed on disk
+    pub fn reload(&self, path: impl Into<String>, target: Target, handle: ExtEventSink) {
+        self.queue(path.into(), true, target, handle)
+    }
+
+    fn queue(&self, path: String, reload: bool, target: Target, handle: ExtEventSink) {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let job = LoadJob { generation, path, reload, target, handle };
+        if self.sender.send(job).is_err() {
+            eprintln!("The image loader thread is not running");
+        }
+    }
+}
+
+fn run_jobs(generation: Arc<AtomicU64>, receiver: Receiver<LoadJob>) {
+    let is_current = |job: &LoadJob| job.generation == generation.load(Ordering::SeqCst);
+    while let Ok(mut job) = receiver.recv() {
+        // Only the most recent request is worth decoding
+        while let Ok(newer) = receiver.try_recv() {
+            job = newer;
+        }
+        if !is_current(&job) {
+            continue;
+        }
+        let result = decode_image(&job.path);
+        if !is_current(&job) {
+            continue;
+        }
+        let loaded = LoadedImage { path: job.path, result, reload: job.reload };
+        if job.handle.submit_command(IMAGE_LOADED, loaded, job.target).is_err() {
+            return;
+        }
+    }
+}
+
+/// Decode the image at `path` to RGBA, with all its frames if it is animated
+///
+/// Photos are turned upright according to their EXIF orientation.
+pub fn decode_image(path: impl AsRef<Path>) -> Result<DecodedImage, LoadError> {
+    let path = path.as_ref();
+    // Read the header first, so huge images are refused before allocating
+    let (width, height) = open_reader(path)?.into_dimensions()?;
+    check_size(width, height)?;
+    let mut reader = open_reader(path)?;
+    let format = reader.format().expect("The format was guessed by open_reader");
+    let metadata = Metadata::read(path);
+    if let Some(mut frames) = animation::decode_frames(path, format, width, height, metadata.orientation)? {
+        return match frames.len() {
+            0 => Err(LoadError::Corrupt("the animation has no frames".to_string())),
+            1 => Ok(DecodedImage { image_buf: frames.remove(0).image_buf, animation: None, document: None, metadata }),
+            _ => Ok(DecodedImage { image_buf: frames[0].image_buf.clone(), animation: Some(Animation::new(frames)), document: None, metadata }),
+        };
+    }
+    if let Some(pages) = pages::decode_pages(path, format, metadata.orientation)? {
+        return Ok(DecodedImage { image_buf: pages[0].clone(), animation: None, document: Some(Document::new(pages)), metadata });
+    }
+    reader.no_limits();
+    let image = reader.decode().map_err(|e| match e {
+        ImageError::Limits(_) => LoadError::TooLarge { width, height },
+        e => e.into(),
+    })?;
+    let image = metadata::apply_orientation(image, metadata.orientation).to_rgba8();
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let image_buf = ImageBuf::from_raw(image.into_raw(), druid::piet::ImageFormat::RgbaSeparate, width, height);
+    Ok(DecodedImage { image_buf: Arc::new(image_buf), animation: None, document: None, metadata })
+}
+
+fn open_reader(path: &Path) -> Result<Reader<BufReader<File>>, LoadError> {
+    let reader = Reader::open(path)?.with_guessed_format()?;
+    if reader.format().is_none() {
+        let extension = path.extension().map(|extension| extension.to_string_lossy().to_uppercase());
+        return Err(LoadError::Unsupported(extension));
+    }
+    Ok(reader)
+}
+
+/// Refuse the empty images and the ones too large to be decoded in memory
+fn check_size(width: u32, height: u32) -> Result<(), LoadError> {
+    if width == 0 || height == 0 {
+        return Err(LoadError::Corrupt("the image is empty".to_string()));
+    }
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(LoadError::TooLarge { width, height });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_to_rgba() {
+        let path = std::env::temp_dir().join(format!("imagine-loader-{}.png", std::process::id()));
+        ::image::RgbImage::from_pixel(3, 2, ::image::Rgb([255, 0, 0])).save(&path).unwrap();
+        let image_buf = decode_image(&path).unwrap().image_buf;
+        assert_eq!((image_buf.width(), image_buf.height()), (3, 2));
+        assert_eq!(&image_buf.raw_pixels()[..4], [255, 0, 0, 255]);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn tells_why_an_image_cannot_be_opened() {
+        let dir = std::env::temp_dir().join(format!("imagine-loader-errors-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        assert_eq!(decode_image(dir.join("missing.png")).err(), Some(LoadError::NotFound));
+
+        std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+        assert_eq!(decode_image(dir.join("notes.txt")).err(), Some(LoadError::Unsupported(Some("TXT".to_string()))));
+
+        // A valid signature followed by garbage
+        std::fs::write(dir.join("broken.png"), b"\x89PNG\r\n\x1a\n garbage").unwrap();
+        assert!(matches!(decode_image(dir.join("broken.png")), Err(LoadError::Corrupt(_))));
+
+        assert_eq!(check_size(100_000, 100_000), Err(LoadError::TooLarge { width: 100_000, height: 100_000 }));
+        assert!(check_size(16_384, 16_384).is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -6,8 +6,22 @@ pub mod dialog;
 pub mod background;
 pub mod colors;
 pub mod ocr;
+pub mod overlay;
+pub mod cli;
+pub mod config;
+pub mod search;
+pub mod export;
+pub mod batch;
+pub mod startup;
+pub mod navigation;
+pub mod watcher;
+pub mod loader;
+pub mod animation;
+pub mod pages;
+pub mod metadata;
 
 
+use clap::Parser;
 use delegate::Delegate;
 
 use prelude::*;
@@ -23,28 +37,54 @@ pub struct AppState {
     pub image_state: image::ImageState,
     pub text: Arc<String>,
     pub key_state: KeyState,
+    pub ocr_options: ocr::options::OcrOptions,
+    /// Recognize the text of the images when they are opened
+    pub auto_ocr: bool,
+    /// Images reachable with the navigation keys, the current one is opened at startup
+    pub navigation: navigation::Navigation,
 }
 
 
 fn main() {
-    // TODO: load the window but don't show it until we have an image
-    // TODO: ask for a file to open if none is provided
+    let cli = cli::Cli::parse();
+    let config = config::Config::load(&cli);
+
+    if let Some(cli::Command::Ocr(args)) = &cli.command {
+        std::process::exit(batch::run(args, &config));
+    }
+
+    if let Some([image, output]) = cli.export.as_deref() {
+        let format = cli.export_format.or_else(|| export::ExportFormat::from_path(output)).unwrap_or_default();
+        let engine = config.build_engine();
+        if let Err(e) = export::export_image(engine.as_ref(), &config.ocr_options, image, output, format) {
+            eprintln!("Failed to export {}: {}", image.display(), e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     let main_window = WindowDesc::new(ui::build_ui())
         .window_size((1200.0, 800.0))
         .menu(ui::make_menu)
-        .title("Imagine");
+        .title(ui::window_title);
 
     #[cfg(target_os = "macos")]
     let main_window = main_window.transparent(true);
     let initial_data = AppState {
-        image_state: image::ImageState::default(),
+        image_state: image::ImageState {
+            zoom_mode: cli.zoom_mode(),
+            ..Default::default()
+        },
         text: Arc::new("Hello World!".to_string()),
         key_state: KeyState::default(),
+        ocr_options: config.ocr_options.clone(),
+        auto_ocr: !cli.no_ocr,
+        navigation: navigation::Navigation::new(cli.images(), navigation::SortOrder::default()),
     };
     
 
     AppLauncher::with_window(main_window)
-        .delegate(Delegate::default())
+        .delegate(Delegate::new(config.build_engine(), config.build_cache()))
         .log_to_console()
         .launch(initial_data)
         .expect("Failed to launch application");
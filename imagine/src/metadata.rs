@@ -0,0 +1,227 @@
+//! EXIF metadata of the photos
+//!
+//! Cameras store the pixels as the sensor saw them, and the Orientation tag
+//! tells how to turn them upright. The loader turns the decoded images with
+//! [`apply_orientation`], so the view, the OCR boxes and the exports all see
+//! the upright image. The other tags are listed in the info panel.
+
+use std::{fs::File, io::BufReader, path::Path};
+
+use ::exif::{DateTime, Exif, In, Reader, Tag, Value};
+use ::image::DynamicImage;
+
+use crate::prelude::*;
+
+/// EXIF metadata of an image, empty for the images without any
+#[derive(Debug, Clone, Default, PartialEq, Data, Lens)]
+pub struct Metadata {
+    /// EXIF orientation from 1 to 8, 0 if the image has none
+    pub orientation: u32,
+    /// Name and value of the tags shown in the info panel, in order
+    pub entries: Arc<Vec<(String, String)>>,
+}
+
+impl Metadata {
+    /// The metadata of the image at `path`, empty if it has none or it cannot be read
+    pub fn read(path: &Path) -> Self {
+        let Ok(file) = File::open(path) else {
+            return Self::default();
+        };
+        match Reader::new().read_from_container(&mut BufReader::new(file)) {
+            Ok(exif) => Self::from_exif(&exif),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn from_exif(exif: &Exif) -> Self {
+        let orientation = exif.get_field(Tag::Orientation, In::PRIMARY).and_then(|field| field.value.get_uint(0)).unwrap_or(0);
+        let with_unit = |tag| exif.get_field(tag, In::PRIMARY).map(|field| field.display_value().with_unit(exif).to_string());
+
+        let mut entries = Vec::new();
+        let make = ascii(exif, Tag::Make);
+        let camera = match (make, ascii(exif, Tag::Model)) {
+            // Most models already start with the brand
+            (Some(make), Some(model)) if !model.starts_with(&make) => Some(format!("{} {}", make, model)),
+            (make, model) => model.or(make),
+        };
+        entries.extend(camera.map(|camera| ("Camera".to_string(), camera)));
+        entries.extend(ascii(exif, Tag::LensModel).map(|lens| ("Lens".to_string(), lens)));
+
+        let exposure = [
+            with_unit(Tag::ExposureTime),
+            with_unit(Tag::FNumber),
+            with_unit(Tag::PhotographicSensitivity).map(|iso| format!("ISO {}", iso)),
+            with_unit(Tag::FocalLength),
+        ];
+        let exposure = exposure.into_iter().flatten().collect::<Vec<_>>();
+        if !exposure.is_empty() {
+            entries.push(("Exposure".to_string(), exposure.join(", ")));
+        }
+
+        let latitude = gps_coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef);
+        let longitude = gps_coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef);
+        if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+            entries.push(("Location".to_string(), format!("{}, {}", latitude, longitude)));
+        }
+        if let Some(altitude) = gps_altitude(exif) {
+            entries.push(("Altitude".to_string(), format!("{:.0} m", altitude)));
+        }
+
+        for (name, tag) in [("Taken", Tag::DateTimeOriginal), ("Digitized", Tag::DateTimeDigitized), ("Modified", Tag::DateTime)] {
+            entries.extend(date_time(exif, tag).map(|date_time| (name.to_string(), date_time)));
+        }
+        Self { orientation, entries: Arc::new(entries) }
+    }
+}
+
+/// Turn `image` upright according to its EXIF orientation
+pub fn apply_orientation(image: DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image,
+    }
+}
+
+/// The text of an ASCII tag, without the padding some cameras add
+fn ascii(exif: &Exif, tag: Tag) -> Option<String> {
+    let Value::Ascii(values) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    let text = values
+        .iter()
+        .map(|value| String::from_utf8_lossy(value).trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string())
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(text).filter(|text| !text.is_empty())
+}
+
+/// `2024-05-01 12:30:00`
+fn date_time(exif: &Exif, tag: Tag) -> Option<String> {
+    let Value::Ascii(values) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    let date_time = DateTime::from_ascii(values.first()?).ok()?;
+    Some(format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        date_time.year, date_time.month, date_time.day, date_time.hour, date_time.minute, date_time.second,
+    ))
+}
+
+/// `48.858370° N`, from the degrees, minutes and seconds of the tag
+fn gps_coordinate(exif: &Exif, tag: Tag, reference: Tag) -> Option<String> {
+    let Value::Rational(parts) = &exif.get_field(tag, In::PRIMARY)?.value else {
+        return None;
+    };
+    if parts.is_empty() {
+        return None;
+    }
+    let degrees = parts.iter().zip([1.0, 60.0, 3600.0]).map(|(part, unit)| part.to_f64() / unit).sum::<f64>();
+    Some(format!("{:.6}° {}", degrees, ascii(exif, reference)?))
+}
+
+/// Meters above the sea level, negative below
+fn gps_altitude(exif: &Exif) -> Option<f64> {
+    let Value::Rational(altitude) = &exif.get_field(Tag::GPSAltitude, In::PRIMARY)?.value else {
+        return None;
+    };
+    let altitude = altitude.first()?.to_f64();
+    let below = exif.get_field(Tag::GPSAltitudeRef, In::PRIMARY).and_then(|field| field.value.get_uint(0)) == Some(1);
+    Some(if below { -altitude } else { altitude })
+}
+
+#[cfg(test)]
+mod tests {
+    use ::exif::{experimental::Writer, Field, Rational};
+    use ::image::{codecs::jpeg::JpegEncoder, GenericImageView, Rgb, RgbImage};
+
+    use super::*;
+
+    fn field(tag: Tag, value: Value) -> Field {
+        Field { tag, ifd_num: In::PRIMARY, value }
+    }
+
+    fn rational(values: &[(u32, u32)]) -> Value {
+        Value::Rational(values.iter().map(|&(num, denom)| Rational { num, denom }).collect())
+    }
+
+    /// A JPEG of a 2×1 photo with `fields` in its EXIF segment
+    fn write_photo(path: &Path, fields: &[Field]) {
+        let mut writer = Writer::new();
+        for field in fields {
+            writer.push_field(field);
+        }
+        let mut tiff = std::io::Cursor::new(Vec::new());
+        writer.write(&mut tiff, false).unwrap();
+        let tiff = tiff.into_inner();
+
+        let mut image = RgbImage::new(2, 1);
+        image.put_pixel(0, 0, Rgb([255, 0, 0]));
+        image.put_pixel(1, 0, Rgb([0, 0, 255]));
+        let mut jpeg = Vec::new();
+        JpegEncoder::new_with_quality(&mut jpeg, 100).encode_image(&image).unwrap();
+
+        // The APP1 segment goes right after the start of image marker
+        let mut segment = vec![0xff, 0xe1];
+        segment.extend_from_slice(&((2 + 6 + tiff.len()) as u16).to_be_bytes());
+        segment.extend_from_slice(b"Exif\0\0");
+        segment.extend_from_slice(&tiff);
+        jpeg.splice(2..2, segment);
+        std::fs::write(path, jpeg).unwrap();
+    }
+
+    #[test]
+    fn reads_the_tags_of_a_photo() {
+        let path = std::env::temp_dir().join(format!("imagine-metadata-{}.jpg", std::process::id()));
+        write_photo(&path, &[
+            field(Tag::Orientation, Value::Short(vec![6])),
+            field(Tag::Make, Value::Ascii(vec![b"Canon".to_vec()])),
+            field(Tag::Model, Value::Ascii(vec![b"Canon EOS 5D".to_vec()])),
+            field(Tag::FNumber, rational(&[(28, 10)])),
+            field(Tag::GPSLatitudeRef, Value::Ascii(vec![b"N".to_vec()])),
+            field(Tag::GPSLatitude, rational(&[(48, 1), (51, 1), (30, 1)])),
+            field(Tag::GPSLongitudeRef, Value::Ascii(vec![b"E".to_vec()])),
+            field(Tag::GPSLongitude, rational(&[(2, 1), (17, 1), (40, 1)])),
+            field(Tag::DateTimeOriginal, Value::Ascii(vec![b"2024:05:01 12:30:00".to_vec()])),
+        ]);
+
+        let metadata = Metadata::read(&path);
+        assert_eq!(metadata.orientation, 6);
+        let entries = metadata.entries.iter().map(|(name, value)| (name.as_str(), value.as_str())).collect::<Vec<_>>();
+        assert_eq!(entries, [
+            ("Camera", "Canon EOS 5D"),
+            ("Exposure", "f/2.8"),
+            ("Location", "48.858333° N, 2.294444° E"),
+            ("Taken", "2024-05-01 12:30:00"),
+        ]);
+
+        // Turned a quarter clockwise, the left pixel is now at the top
+        let image = loader::decode_image(&path).unwrap();
+        assert_eq!((image.image_buf.width(), image.image_buf.height()), (1, 2));
+        assert_eq!(image.metadata, metadata);
+        assert!(image.image_buf.raw_pixels()[0] > 200);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn turns_the_images_upright() {
+        // Stored as the sensor saw it: 1 2 3 on top of 4 5 6
+        let image = DynamicImage::ImageLuma8(::image::GrayImage::from_raw(3, 2, vec![1, 2, 3, 4, 5, 6]).unwrap());
+        let pixels = |orientation| {
+            let image = apply_orientation(image.clone(), orientation);
+            (image.dimensions(), image.to_luma8().into_raw())
+        };
+        assert_eq!(pixels(1), ((3, 2), vec![1, 2, 3, 4, 5, 6]));
+        assert_eq!(pixels(3), ((3, 2), vec![6, 5, 4, 3, 2, 1]));
+        assert_eq!(pixels(5), ((2, 3), vec![1, 4, 2, 5, 3, 6]));
+        assert_eq!(pixels(6), ((2, 3), vec![4, 1, 5, 2, 6, 3]));
+        assert_eq!(pixels(7), ((2, 3), vec![6, 3, 5, 2, 4, 1]));
+        assert_eq!(pixels(8), ((2, 3), vec![3, 6, 2, 5, 1, 4]));
+    }
+}
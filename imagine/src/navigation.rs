@@ -0,0 +1,344 @@
+//! Navigation through the images of a folder
+//!
+//! Opening an image lists the supported images of its directory, unless it is
+//! already part of the list, such as the images given on the command line. A
+//! single image given on the command line comes with its folder, like any
+//! other opened image.
+//! The arrow keys, PageUp/PageDown and Home/End then open the previous, next,
+//! first or last image of the list. PageUp/PageDown go through the pages of a
+//! document first.
+
+use std::{
+    cmp::Ordering,
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use druid::{im::Vector, keyboard_types::Key, widget::Controller};
+
+use crate::prelude::*;
+
+use self::{delegate::NAVIGATE, dialog::is_supported_image};
+
+/// Order of the images in the navigation list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Data)]
+pub enum SortOrder {
+    /// By file name, with the numbers compared by value (`2.png` before `10.png`)
+    #[default]
+    Name,
+    /// Oldest modification first
+    Modified,
+    /// Smallest file first
+    Size,
+}
+
+impl SortOrder {
+    pub const ALL: [SortOrder; 3] = [SortOrder::Name, SortOrder::Modified, SortOrder::Size];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SortOrder::Name => "Name",
+            SortOrder::Modified => "Modification date",
+            SortOrder::Size => "Size",
+        }
+    }
+}
+
+/// A move in the navigation list
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NavigationStep {
+    Previous,
+    Next,
+    First,
+    Last,
+    /// The previous page of a document, or the previous image on the first page
+    PreviousPage,
+    /// The next page of a document, or the next image on the last page
+    NextPage,
+}
+
+impl NavigationStep {
+    fn for_key(key: &Key) -> Option<Self> {
+        match key {
+            Key::ArrowLeft => Some(NavigationStep::Previous),
+            Key::ArrowRight => Some(NavigationStep::Next),
+            Key::PageUp => Some(NavigationStep::PreviousPage),
+            Key::PageDown => Some(NavigationStep::NextPage),
+            Key::Home => Some(NavigationStep::First),
+            Key::End => Some(NavigationStep::Last),
+            _ => None,
+        }
+    }
+}
+
+/// The images that can be reached from the current one
+#[derive(Clone, Data, Lens, Default)]
+pub struct Navigation {
+    /// Paths of the images, in the sort order
+    pub files: Vector<String>,
+    /// Index of the current image in `files`
+    pub current: usize,
+    pub sort: SortOrder,
+}
+
+impl Navigation {
+    /// A list of `paths` in the sort order, starting at the first one given
+    ///
+    /// A single image, as when the viewer opens a file, comes with the other
+    /// images of its directory.
+    pub fn new(paths: Vec<PathBuf>, sort: SortOrder) -> Self {
+        let mut navigation = Self { files: Vector::new(), current: 0, sort };
+        let Some(first) = paths.first().map(|path| path.to_string_lossy().into_owned()) else {
+            return navigation;
+        };
+        if paths.len() == 1 {
+            navigation.select(&first);
+        } else {
+            navigation.set_files(paths);
+            navigation.current = navigation.files.index_of(&first).unwrap_or(0);
+        }
+        navigation
+    }
+
+    fn set_files(&mut self, mut paths: Vec<PathBuf>) {
+        sort_paths(&mut paths, self.sort);
+        self.files = paths.into_iter().map(|path| path.to_string_lossy().into_owned()).collect();
+    }
+
+    /// Path of the current image, if the list is not empty
+    pub fn current_file(&self) -> Option<&String> {
+        self.files.get(self.current)
+    }
+
+    /// Make `path` the current image, listing its directory if it is not in the list
+    pub fn select(&mut self, path: &str) {
+        if let Some(index) = self.files.index_of(&path.to_string()) {
+            self.current = index;
+            return;
+        }
+        let path = Path::new(path);
+        let mut paths = scan_directory(path.parent().unwrap_or(Path::new(""))).unwrap_or_else(|e| {
+            eprintln!("Failed to list the folder of {}: {}", path.display(), e);
+            Vec::new()
+        });
+        // An image with an unusual extension still has its place in the list
+        if !paths.iter().any(|other| other == path) {
+            paths.push(path.to_path_buf());
+        }
+        self.set_files(paths);
+        self.current = self.files.index_of(&path.to_string_lossy().into_owned()).unwrap_or(0);
+    }
+
+    /// Drop the removed images and add the new ones of the folder of the current image
+    pub fn refresh(&mut self) {
+        let Some(current) = self.current_file().cloned() else {
+            return;
+        };
+        let current = PathBuf::from(current);
+        // The current image is kept even if it was removed, it is still shown
+        let mut paths = self.files.iter().map(PathBuf::from).filter(|path| path.exists() || *path == current).collect::<Vec<_>>();
+        match scan_directory(current.parent().unwrap_or(Path::new(""))) {
+            Ok(scanned) => {
+                for path in scanned {
+                    if !paths.contains(&path) {
+                        paths.push(path);
+                    }
+                }
+            },
+            Err(e) => eprintln!("Failed to list the folder of {}: {}", current.display(), e),
+        }
+        self.set_files(paths);
+        self.current = self.files.index_of(&current.to_string_lossy().into_owned()).unwrap_or(0);
+    }
+
+    /// Path of the image `step` leads to, `None` at the ends of the list
+    pub fn target(&self, step: NavigationStep) -> Option<String> {
+        let last = self.files.len().checked_sub(1)?;
+        let index = match step {
+            NavigationStep::Previous | NavigationStep::PreviousPage => self.current.checked_sub(1)?,
+            NavigationStep::Next | NavigationStep::NextPage if self.current < last => self.current + 1,
+            NavigationStep::Next | NavigationStep::NextPage => return None,
+            NavigationStep::First => 0,
+            NavigationStep::Last => last,
+        };
+        (index != self.current).then(|| self.files[index].clone())
+    }
+
+    /// Sort the list again, keeping the current image
+    pub fn set_sort(&mut self, sort: SortOrder) {
+        let current = self.current_file().cloned();
+        self.sort = sort;
+        self.set_files(self.files.iter().map(PathBuf::from).collect());
+        if let Some(index) = current.and_then(|current| self.files.index_of(&current)) {
+            self.current = index;
+        }
+    }
+
+    /// `3/12` when there are several images
+    pub fn position(&self) -> Option<String> {
+        (self.files.len() > 1).then(|| format!("{}/{}", self.current + 1, self.files.len()))
+    }
+}
+
+/// Supported images of `directory`, in no particular order
+///
+/// The paths start with `directory`, an empty one stands for the current directory.
+pub fn scan_directory(directory: &Path) -> io::Result<Vec<PathBuf>> {
+    let read_dir = if directory.as_os_str().is_empty() { fs::read_dir(".") } else { fs::read_dir(directory) };
+    Ok(read_dir?
+        .filter_map(Result::ok)
+        .map(|entry| directory.join(entry.file_name()))
+        .filter(|path| path.is_file() && is_supported_image(path))
+        .collect())
+}
+
+/// Sort `paths` in `order`, ties and unreadable files are sorted by name
+pub fn sort_paths(paths: &mut [PathBuf], order: SortOrder) {
+    // The sorts are stable, sorting by name first breaks the ties
+    paths.sort_by(|a, b| natural_cmp(&a.to_string_lossy(), &b.to_string_lossy()));
+    match order {
+        SortOrder::Name => {},
+        SortOrder::Modified => {
+            paths.sort_by_cached_key(|path| fs::metadata(path).and_then(|metadata| metadata.modified()).unwrap_or(SystemTime::UNIX_EPOCH));
+        },
+        SortOrder::Size => paths.sort_by_cached_key(|path| fs::metadata(path).map(|metadata| metadata.len()).unwrap_or(0)),
+    }
+}
+
+/// Compare two names ignoring case, with the runs of digits compared by value
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    natural_cmp_ignoring_case(a, b).then_with(|| a.cmp(b))
+}
+
+fn natural_cmp_ignoring_case(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let take_number = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut digits = String::new();
+                    while let Some(c) = chars.next_if(char::is_ascii_digit) {
+                        digits.push(c);
+                    }
+                    digits
+                };
+                let (x, y) = (take_number(&mut a), take_number(&mut b));
+                let (x_value, y_value) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = x_value.len().cmp(&y_value.len()).then_with(|| x_value.cmp(y_value)).then_with(|| x.len().cmp(&y.len()));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            },
+            (Some(x), Some(y)) => {
+                let ordering = x.to_lowercase().cmp(y.to_lowercase());
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+                a.next();
+                b.next();
+            },
+        }
+    }
+}
+
+/// Turns the navigation keys the focused widget did not use into [`NAVIGATE`] commands
+pub struct NavigationController;
+
+impl<W: Widget<AppState>> Controller<AppState, W> for NavigationController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut AppState, env: &Env) {
+        child.event(ctx, event, data, env);
+        if let Event::KeyDown(key_event) = event {
+            if ctx.is_handled() || key_event.mods.ctrl() || key_event.mods.alt() || key_event.mods.meta() {
+                return;
+            }
+            if let Some(step) = NavigationStep::for_key(&key_event.key) {
+                ctx.submit_command(NAVIGATE.with(step));
+                ctx.set_handled();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn navigation(files: &[&str], current: usize) -> Navigation {
+        Navigation { files: files.iter().map(|file| file.to_string()).collect(), current, sort: SortOrder::Name }
+    }
+
+    #[test]
+    fn compares_numbers_by_value() {
+        let mut names = vec!["shot10.png", "Shot2.png", "shot1.png", "shot02.png", "a.png"];
+        names.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(names, ["a.png", "shot1.png", "Shot2.png", "shot02.png", "shot10.png"]);
+    }
+
+    #[test]
+    fn stops_at_the_ends_of_the_list() {
+        let navigation = navigation(&["a", "b", "c"], 0);
+        assert_eq!(navigation.target(NavigationStep::Previous), None);
+        assert_eq!(navigation.target(NavigationStep::First), None);
+        assert_eq!(navigation.target(NavigationStep::Next).as_deref(), Some("b"));
+        assert_eq!(navigation.target(NavigationStep::Last).as_deref(), Some("c"));
+        assert_eq!(navigation.position().as_deref(), Some("1/3"));
+        assert_eq!(Navigation::default().target(NavigationStep::Next), None);
+    }
+
+    #[test]
+    fn starts_at_the_first_image_given() {
+        let navigation = Navigation::new(vec![PathBuf::from("b.png"), PathBuf::from("a.png")], SortOrder::Name);
+        assert_eq!(navigation.files, Vector::from(vec!["a.png".to_string(), "b.png".to_string()]));
+        assert_eq!(navigation.current_file().map(String::as_str), Some("b.png"));
+        assert_eq!(Navigation::new(Vec::new(), SortOrder::Name).current_file(), None);
+    }
+
+    #[test]
+    fn lists_the_directory_of_a_single_image_at_startup() {
+        let dir = std::env::temp_dir().join(format!("imagine-navigation-startup-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        for name in ["a.png", "b.png", "c.png"] {
+            fs::write(dir.join(name), b"").unwrap();
+        }
+
+        let navigation = Navigation::new(vec![dir.join("b.png")], SortOrder::Name);
+        assert_eq!(navigation.files.len(), 3);
+        assert_eq!(navigation.current_file(), Some(&dir.join("b.png").to_string_lossy().into_owned()));
+        assert_eq!(navigation.target(NavigationStep::Previous), Some(dir.join("a.png").to_string_lossy().into_owned()));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn lists_the_directory_of_a_new_image() {
+        let dir = std::env::temp_dir().join(format!("imagine-navigation-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("10.png"), b"1234").unwrap();
+        fs::write(dir.join("9.jpg"), b"1").unwrap();
+        fs::write(dir.join("notes.txt"), b"").unwrap();
+        let name = |file: &String| Path::new(file).file_name().unwrap().to_string_lossy().into_owned();
+
+        let mut navigation = Navigation::default();
+        navigation.select(&dir.join("10.png").to_string_lossy());
+        assert_eq!(navigation.files.iter().map(name).collect::<Vec<_>>(), ["9.jpg", "10.png"]);
+        assert_eq!(navigation.current, 1);
+
+        navigation.set_sort(SortOrder::Size);
+        assert_eq!(navigation.files.iter().map(name).collect::<Vec<_>>(), ["9.jpg", "10.png"]);
+        navigation.set_sort(SortOrder::Name);
+        navigation.select(&dir.join("9.jpg").to_string_lossy());
+        assert_eq!((navigation.files.len(), navigation.current), (2, 0));
+
+        fs::write(dir.join("1.png"), b"").unwrap();
+        fs::remove_file(dir.join("10.png")).unwrap();
+        navigation.refresh();
+        assert_eq!(navigation.files.iter().map(name).collect::<Vec<_>>(), ["1.png", "9.jpg"]);
+        assert_eq!(navigation.current, 1);
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
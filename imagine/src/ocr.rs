@@ -1,78 +1,177 @@
-use std::{process::Command, path::Path};
+use druid::{Affine, Data, Point, Rect, Size, Vec2, im::Vector};
 
-use druid::{Data, Rect, Point, im::Vector};
+pub mod engine;
+pub mod easyocr;
+pub mod tesseract;
+pub mod mock;
+pub mod parser;
+pub mod protocol;
+pub mod worker;
+pub mod jobs;
+pub mod layout;
+pub mod cache;
+pub mod options;
+pub mod region;
 
+use self::parser::{OcrDiagnostic, ParsedOcr};
 
 #[derive(Debug, Clone, Data)]
 pub struct OcrTextBox {
     pub boxes: Vector<Point>,
     pub text: String,
-    confidence: f64,
+    /// Score given by the engine, between 0 and 1
+    pub confidence: f64,
 }
 
 impl OcrTextBox {
-    fn parse_ocr_text_box(input: &str) -> Result<OcrTextBox, String> {
-        let parts: Vec<&str> = input.split(';').collect();
-        if parts.len() != 3 {
-            return Err("Invalid input format".to_string());
-        }
+    /// Smallest axis-aligned rect containing the polygon, in image coordinates
+    pub fn bounding_rect(&self) -> Rect {
+        let mut points = self.boxes.iter();
+        let Some(first) = points.next() else {
+            return Rect::ZERO;
+        };
+        points.fold(Rect::from_points(*first, *first), |rect, point| rect.union_pt(*point))
+    }
 
-        // Parse points
-        // Remove ( and )
-        let points_str = parts[0].trim_start_matches('(').trim_end_matches(')');
-        let boxes = points_str.split('-').map(|point| {
-            let point = point.trim_start_matches('[').trim_end_matches(']').replace(' ', "");
-            let point: Vec<&str> = point.split(',').collect();
-
-            if point.len() != 2 {
-                panic!("Invalid point format");
-            }
-            
-            Point::new(
-                point[0].parse::<i32>().unwrap() as f64,
-                point[1].parse::<i32>().unwrap() as f64,
-            )
-        });
-
-        // Parse text
-        let text = parts[1].to_string();
-
-        // Parse confidence
-        let confidence = parts[2].parse::<f64>().unwrap();
-    
-        Ok(Self {
-            boxes: boxes.collect(),
-            text,
-            confidence,
-        })
+    /// Average top and left edges of the polygon, from its top left corner
+    fn edges(&self) -> Option<(Vec2, Vec2)> {
+        let [p0, p1, p2, p3] = [0, 1, 2, 3].map(|i| self.boxes.get(i).copied());
+        let (p0, p1, p2, p3) = (p0?, p1?, p2?, p3?);
+        let top = ((p1 - p0) + (p2 - p3)) / 2.0;
+        let side = ((p3 - p0) + (p2 - p1)) / 2.0;
+        Some((top, side))
+    }
+
+    /// Angle of the text baseline, in radians, clockwise from the horizontal
+    pub fn orientation(&self) -> f64 {
+        self.edges().map(|(top, _)| top.atan2()).unwrap_or(0.0)
+    }
+
+    /// Size of the box along its own axes and the transform that maps the
+    /// rect `(0, 0, width, height)` onto the polygon in image coordinates
+    ///
+    /// The polygon is approximated by a parallelogram, which covers rotated
+    /// and skewed text. Degenerate polygons use their bounding rect.
+    pub fn transform(&self) -> (Affine, Size) {
+        let rect = self.bounding_rect();
+        let fallback = (Affine::translate(rect.origin().to_vec2()), rect.size());
+        let Some((top, side)) = self.edges() else {
+            return fallback;
+        };
+        let size = Size::new(top.hypot(), side.hypot());
+        if size.width < f64::EPSILON || size.height < f64::EPSILON {
+            return fallback;
+        }
+        let (x_axis, y_axis) = (top / size.width, side / size.height);
+        let origin = self.boxes[0];
+        (Affine::new([x_axis.x, x_axis.y, y_axis.x, y_axis.y, origin.x, origin.y]), size)
     }
 }
 
-#[derive(Clone, Data)]
+#[derive(Debug, Clone, Data)]
 pub struct Ocr {
-    img_path: String,
+    /// Path of the image the text was recognized in
+    pub img_path: String,
+    /// Page of the image, for the images with several pages
+    pub page: usize,
+    /// Name of the engine that produced the results
+    pub engine: String,
+    pub languages: Vector<String>,
+    /// Size of the image the coordinates refer to
+    pub image_size: Size,
     pub content: Vector<OcrTextBox>,
+    /// Lines of the engine output that could not be parsed
+    pub diagnostics: Vector<OcrDiagnostic>,
 }
 
 impl Ocr {
-    pub fn get_text(path: impl AsRef<Path>) -> Self {
-        let path = path.as_ref().to_str().unwrap();
-        let output = Command::new("python3")
-            .arg("python/get_text.py")
-            .arg(path)
-            .output()
-            .expect("Échec de l'exécution du script Python");
-        let binding = output.stdout.clone();
-        let binding = String::from_utf8_lossy(&binding);
-        let rows = binding.lines();
-        let mut content = Vec::new();
-        for row in rows {
-            content.push(OcrTextBox::parse_ocr_text_box(row).unwrap());
-        }
-        println!("stderr: {}", String::from_utf8_lossy(&output.stderr));
+    pub fn new(img_path: impl Into<String>, content: impl Into<Vector<OcrTextBox>>) -> Self {
         Self {
-            img_path: path.to_string(),
+            img_path: img_path.into(),
+            page: 0,
+            engine: String::new(),
+            languages: Vector::new(),
+            image_size: Size::ZERO,
             content: content.into(),
+            diagnostics: Vector::new(),
         }
     }
-}
\ No newline at end of file
+
+    /// Build the result of a parsed engine output, keeping its diagnostics
+    pub fn from_parsed(img_path: impl Into<String>, parsed: ParsedOcr) -> Self {
+        let mut ocr = Self::new(img_path, parsed.content);
+        ocr.diagnostics = parsed.diagnostics.into();
+        ocr
+    }
+
+    pub fn with_engine(mut self, engine: impl Into<String>) -> Self {
+        self.engine = engine.into();
+        self
+    }
+
+    pub fn with_languages(mut self, languages: impl IntoIterator<Item = String>) -> Self {
+        self.languages = languages.into_iter().collect();
+        self
+    }
+
+    pub fn with_image_size(mut self, image_size: Size) -> Self {
+        self.image_size = image_size;
+        self
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// A box with the corners `points`, clockwise from the top left of the text
+    pub fn text_box(text: &str, points: [(f64, f64); 4]) -> OcrTextBox {
+        OcrTextBox {
+            boxes: points.iter().map(|(x, y)| Point::new(*x, *y)).collect(),
+            text: text.to_string(),
+            confidence: 1.0,
+        }
+    }
+
+    /// An axis-aligned box, as most words are
+    pub fn word(text: &str, x: f64, y: f64, width: f64, height: f64) -> OcrTextBox {
+        text_box(text, [(x, y), (x + width, y), (x + width, y + height), (x, y + height)])
+    }
+
+    fn assert_near(a: Point, b: Point) {
+        assert!((a - b).hypot() < 1e-9, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn maps_axis_aligned_boxes() {
+        let (transform, size) = text_box("", [(10.0, 20.0), (50.0, 20.0), (50.0, 30.0), (10.0, 30.0)]).transform();
+        assert_eq!(size, Size::new(40.0, 10.0));
+        assert_near(transform * Point::ORIGIN, Point::new(10.0, 20.0));
+        assert_near(transform * Point::new(40.0, 10.0), Point::new(50.0, 30.0));
+    }
+
+    #[test]
+    fn maps_rotated_boxes() {
+        // A 20x10 box rotated by 90 degrees clockwise around (0, 0)
+        let text_box = text_box("", [(0.0, 0.0), (0.0, 20.0), (-10.0, 20.0), (-10.0, 0.0)]);
+        let (transform, size) = text_box.transform();
+        assert_eq!(size, Size::new(20.0, 10.0));
+        assert!((text_box.orientation() - std::f64::consts::FRAC_PI_2).abs() < 1e-9);
+        assert_near(transform * Point::new(20.0, 0.0), Point::new(0.0, 20.0));
+        assert_near(transform * Point::new(20.0, 10.0), Point::new(-10.0, 20.0));
+    }
+
+    #[test]
+    fn maps_skewed_boxes() {
+        let (transform, size) = text_box("", [(0.0, 0.0), (40.0, 0.0), (45.0, 10.0), (5.0, 10.0)]).transform();
+        assert_near(transform * Point::new(0.0, size.height), Point::new(5.0, 10.0));
+        assert_near(transform * Point::new(size.width, size.height), Point::new(45.0, 10.0));
+    }
+
+    #[test]
+    fn falls_back_to_the_bounding_rect() {
+        let (transform, size) = text_box("", [(3.0, 4.0), (3.0, 4.0), (3.0, 4.0), (3.0, 4.0)]).transform();
+        assert_eq!(size, Size::ZERO);
+        assert_near(transform * Point::ORIGIN, Point::new(3.0, 4.0));
+    }
+}
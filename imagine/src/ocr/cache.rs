@@ -0,0 +1,204 @@
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use sha2::{Digest, Sha256};
+
+use super::{engine::OcrEngine, options::OcrOptions, protocol::OcrDocument, Ocr};
+
+/// Default size limit of the cache
+pub const DEFAULT_MAX_BYTES: u64 = 64 * 1024 * 1024;
+
+/// OCR results stored on disk, one JSON document per image
+///
+/// Entries are keyed by a hash of the image content and of the engine name,
+/// version and languages, so a renamed file is still a hit and a change of
+/// engine settings is a miss. The least recently used entries are removed
+/// when the cache grows over its size limit.
+pub struct OcrCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl OcrCache {
+    pub fn new(dir: impl Into<PathBuf>, max_bytes: u64) -> Self {
+        Self { dir: dir.into(), max_bytes }
+    }
+
+    /// `$XDG_CACHE_HOME/imagine/ocr`, or `~/.cache/imagine/ocr`
+    pub fn default_dir() -> Option<PathBuf> {
+        let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_absolute())
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(cache_dir.join("imagine").join("ocr"))
+    }
+
+    /// Key of the results of `engine` for the image at `path`
+    ///
+    /// The device is left out, it does not change what is recognized, and so
+    /// is the order the languages were picked in.
+    pub fn key(path: &Path, engine: &dyn OcrEngine, options: &OcrOptions) -> io::Result<String> {
+        let mut hasher = Sha256::new();
+        hasher.update(fs::read(path)?);
+        let mut languages = options.languages.iter().cloned().collect::<Vec<_>>();
+        languages.sort();
+        languages.dedup();
+        let languages = languages.join(",");
+        for part in [engine.name().to_string(), engine.version(), languages] {
+            hasher.update([0]);
+            hasher.update(part.as_bytes());
+        }
+        Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+
+    fn entry_path(&self, key: &str) -> PathBuf {
+        self.dir.join(key).with_extension("json")
+    }
+
+    /// The cached results of `key`, attributed to the image at `img_path`
+    pub fn get(&self, key: &str, img_path: &str) -> Option<Ocr> {
+        let entry = self.entry_path(key);
+        let content = fs::read_to_string(&entry).ok()?;
+        let ocr = serde_json::from_str::<OcrDocument>(&content)
+            .ok()
+            .and_then(|document| document.into_ocr(img_path).ok());
+        match &ocr {
+            // Mark the entry as recently used
+            Some(_) => {
+                let _ = fs::File::options().append(true).open(&entry).and_then(|file| file.set_modified(SystemTime::now()));
+            },
+            // Written by an incompatible version
+            None => {
+                let _ = fs::remove_file(&entry);
+            },
+        }
+        ocr
+    }
+
+    /// Store the results of `key`, evicting old entries if the cache is full
+    pub fn put(&self, key: &str, ocr: &Ocr) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        // Write then rename, so a reader never sees a partial entry
+        let temporary = self.dir.join(key).with_extension("tmp");
+        fs::write(&temporary, ocr.to_json())?;
+        fs::rename(&temporary, self.entry_path(key))?;
+        self.evict()
+    }
+
+    /// Remove the entry of `key`, if any
+    pub fn remove(&self, key: &str) -> io::Result<()> {
+        match fs::remove_file(self.entry_path(key)) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        }
+    }
+
+    /// Remove the least recently used entries until the cache fits its size limit
+    fn evict(&self) -> io::Result<()> {
+        let mut entries = fs::read_dir(&self.dir)?
+            .filter_map(Result::ok)
+            .filter(|entry| entry.path().extension().is_some_and(|extension| extension == "json"))
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok()?;
+                Some((metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH), metadata.len(), entry.path()))
+            })
+            .collect::<Vec<_>>();
+        let mut total = entries.iter().map(|(_, len, _)| len).sum::<u64>();
+        entries.sort_by_key(|(modified, _, _)| *modified);
+        for (_, len, path) in entries {
+            if total <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(path)?;
+            total -= len;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use druid::Size;
+
+    use super::*;
+    use crate::ocr::mock::MockEngine;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("imagine-cache-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn stores_and_restores_results() {
+        let dir = temp_dir("hit");
+        let image = dir.join("image.png");
+        fs::write(&image, b"not really a png").unwrap();
+        let cache = OcrCache::new(dir.join("cache"), DEFAULT_MAX_BYTES);
+        let key = OcrCache::key(&image, &MockEngine, &OcrOptions::default()).unwrap();
+        assert!(cache.get(&key, "image.png").is_none());
+
+        let ocr = Ocr::new("image.png", Vec::new()).with_engine("mock").with_image_size(Size::new(4.0, 2.0));
+        cache.put(&key, &ocr).unwrap();
+        let cached = cache.get(&key, "renamed.png").unwrap();
+        assert_eq!(cached.img_path, "renamed.png");
+        assert_eq!(cached.image_size, Size::new(4.0, 2.0));
+
+        cache.remove(&key).unwrap();
+        assert!(cache.get(&key, "image.png").is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keys_depend_on_the_content_and_options() {
+        let dir = temp_dir("key");
+        let (a, b) = (dir.join("a.png"), dir.join("b.png"));
+        fs::write(&a, b"same").unwrap();
+        fs::write(&b, b"same").unwrap();
+        let options = OcrOptions::default();
+        assert_eq!(OcrCache::key(&a, &MockEngine, &options).unwrap(), OcrCache::key(&b, &MockEngine, &options).unwrap());
+        let mut german = options.clone();
+        german.toggle_language("de");
+        assert_ne!(OcrCache::key(&a, &MockEngine, &options).unwrap(), OcrCache::key(&a, &MockEngine, &german).unwrap());
+        fs::write(&b, b"other").unwrap();
+        assert_ne!(OcrCache::key(&a, &MockEngine, &options).unwrap(), OcrCache::key(&b, &MockEngine, &options).unwrap());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn keys_ignore_the_order_of_the_languages() {
+        let dir = temp_dir("languages");
+        let image = dir.join("image.png");
+        fs::write(&image, b"image").unwrap();
+        let with_languages = |languages: &[&str]| OcrOptions {
+            languages: languages.iter().map(|language| language.to_string()).collect(),
+            ..OcrOptions::default()
+        };
+        let key = |languages: &[&str]| OcrCache::key(&image, &MockEngine, &with_languages(languages)).unwrap();
+        assert_eq!(key(&["fr", "en"]), key(&["en", "fr"]));
+        assert_eq!(key(&["en", "fr", "en"]), key(&["en", "fr"]));
+        assert_ne!(key(&["en"]), key(&["en", "fr"]));
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn evicts_entries_over_the_limit() {
+        let dir = temp_dir("evict");
+        let ocr = Ocr::new("image.png", Vec::new());
+        let cache = OcrCache::new(&dir, ocr.to_json().len() as u64);
+        cache.put("old", &ocr).unwrap();
+        fs::File::options()
+            .append(true)
+            .open(dir.join("old.json"))
+            .and_then(|file| file.set_modified(SystemTime::now() - std::time::Duration::from_secs(60)))
+            .unwrap();
+        cache.put("new", &ocr).unwrap();
+        assert!(!dir.join("old.json").exists());
+        assert!(dir.join("new.json").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
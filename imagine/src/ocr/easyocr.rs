@@ -0,0 +1,64 @@
+use std::path::{Path, PathBuf};
+
+use super::{engine::{OcrEngine, OcrError}, options::{OcrDevice, OcrOptions}, protocol::{OcrDocument, WorkerMethod}, worker::OcrWorker, Ocr};
+
+/// Name of the helper script shipped in the `python` directory
+const SCRIPT_NAME: &str = "get_text.py";
+
+/// Runs the easyocr helper script as a persistent worker
+///
+/// The python interpreter and the easyocr model are loaded by the first
+/// request and reused for the following images.
+pub struct EasyOcrEngine {
+    worker: OcrWorker,
+}
+
+impl EasyOcrEngine {
+    pub fn new(python: impl Into<String>, script: impl Into<PathBuf>) -> Self {
+        let script = script.into().to_string_lossy().into_owned();
+        Self {
+            worker: OcrWorker::new(python, vec![script, "--server".to_string()]),
+        }
+    }
+
+    /// Look for the helper script next to the executable, then in the source tree
+    pub fn find_script() -> PathBuf {
+        let mut candidates = Vec::new();
+        if let Some(exe_dir) = std::env::current_exe().ok().and_then(|exe| exe.parent().map(Path::to_path_buf)) {
+            candidates.push(exe_dir.join("python").join(SCRIPT_NAME));
+            candidates.push(exe_dir.join("../share/imagine").join(SCRIPT_NAME));
+        }
+        candidates.push(Path::new(env!("CARGO_MANIFEST_DIR")).join("python").join(SCRIPT_NAME));
+
+        candidates
+            .iter()
+            .find(|path| path.is_file())
+            .unwrap_or(candidates.last().unwrap())
+            .clone()
+    }
+}
+
+impl OcrEngine for EasyOcrEngine {
+    fn name(&self) -> &str {
+        "easyocr"
+    }
+
+    fn recognize(&self, path: &Path, options: &OcrOptions) -> Result<Ocr, OcrError> {
+        let path = path.to_str().ok_or(OcrError::InvalidPath)?;
+        let result = self.worker.request(WorkerMethod::Ocr {
+            path: path.to_string(),
+            languages: options.languages.iter().cloned().collect(),
+            gpu: options.device == OcrDevice::Gpu,
+        })?;
+        let document: OcrDocument = serde_json::from_value(result).map_err(|e| OcrError::Worker(e.to_string()))?;
+        document.into_ocr(path).map_err(OcrError::Output)
+    }
+
+    fn health(&self) -> String {
+        match self.worker.restarts() {
+            0 => self.worker.status().to_string(),
+            restarts => format!("{}, restarted {} times", self.worker.status(), restarts),
+        }
+    }
+
+}
@@ -0,0 +1,112 @@
+use std::{fmt, io, path::Path, process::ExitStatus, str::FromStr, time::Duration};
+
+use super::{options::OcrOptions, parser::OcrParseError, Ocr};
+
+/// A backend able to recognize the text of an image file.
+///
+/// Engines are shared between the UI and the OCR threads, so they must be
+/// `Send + Sync`.
+pub trait OcrEngine: Send + Sync {
+    /// Short identifier of the engine (e.g. `easyocr`)
+    fn name(&self) -> &str;
+
+    /// Run the recognition on the image at `path`
+    fn recognize(&self, path: &Path, options: &OcrOptions) -> Result<Ocr, OcrError>;
+
+    /// Human readable health of the engine
+    fn health(&self) -> String {
+        "ready".to_string()
+    }
+
+    /// Version of the engine, results of another version are not reused
+    fn version(&self) -> String {
+        env!("CARGO_PKG_VERSION").to_string()
+    }
+}
+
+/// The available OCR backends
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EngineKind {
+    #[default]
+    EasyOcr,
+    Tesseract,
+    Mock,
+}
+
+impl EngineKind {
+    pub const ALL: [EngineKind; 3] = [EngineKind::EasyOcr, EngineKind::Tesseract, EngineKind::Mock];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EngineKind::EasyOcr => "easyocr",
+            EngineKind::Tesseract => "tesseract",
+            EngineKind::Mock => "mock",
+        }
+    }
+}
+
+impl fmt::Display for EngineKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for EngineKind {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EngineKind::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| format!("unknown OCR engine `{}` (expected one of: easyocr, tesseract, mock)", s))
+    }
+}
+
+/// Error raised when an engine could not produce a result
+#[derive(Debug)]
+pub enum OcrError {
+    /// The image path is not valid UTF-8
+    InvalidPath,
+    /// The engine program could not be started
+    Spawn { program: String, source: io::Error },
+    /// The engine program exited with a failure status
+    Failed { program: String, status: ExitStatus, stderr: String },
+    /// The engine could not read the image
+    Image(String),
+    /// The engine output could not be decoded
+    Output(OcrParseError),
+    /// The OCR worker process failed or returned an error
+    Worker(String),
+    /// The OCR worker did not answer in time and was stopped
+    Timeout(Duration),
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OcrError::InvalidPath => write!(f, "image path is not valid UTF-8"),
+            OcrError::Spawn { program, source } => write!(f, "failed to run `{}`: {}", program, source),
+            OcrError::Failed { program, status, stderr } => {
+                write!(f, "`{}` exited with {}", program, status)?;
+                if !stderr.trim().is_empty() {
+                    write!(f, ": {}", stderr.trim())?;
+                }
+                Ok(())
+            }
+            OcrError::Image(e) => write!(f, "failed to read image: {}", e),
+            OcrError::Output(e) => write!(f, "failed to decode engine output: {}", e),
+            OcrError::Worker(e) => write!(f, "OCR worker error: {}", e),
+            OcrError::Timeout(timeout) => write!(f, "the OCR worker did not answer within {} s", timeout.as_secs()),
+        }
+    }
+}
+
+impl std::error::Error for OcrError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            OcrError::Spawn { source, .. } => Some(source),
+            OcrError::Output(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,202 @@
+use std::{
+    ops::Range,
+    path::Path,
+    sync::{atomic::{AtomicU64, Ordering}, mpsc::{self, Receiver, Sender}, Arc},
+};
+
+use druid::{ExtEventSink, Rect, Size, Target};
+
+use crate::{delegate::{OCR_FAILED, SEND_OCR, SEND_REGION_OCR}, metadata::Metadata, pages::{open_page, page_count}};
+
+use super::{cache::OcrCache, engine::{OcrEngine, OcrError}, options::OcrOptions, region::{recognize_image, recognize_region}, Ocr};
+
+/// An OCR request, tagged with the generation it was submitted in
+struct OcrJob {
+    generation: u64,
+    path: String,
+    /// Pages to recognize one after the other, each result is sent on its own
+    pages: Range<usize>,
+    options: OcrOptions,
+    /// Part of the image to recognize, in image coordinates, the whole image if `None`
+    region: Option<Rect>,
+    /// Skip the cache and replace its entry
+    force: bool,
+    handle: ExtEventSink,
+}
+
+/// Error of an OCR job, sent to the UI with [`OCR_FAILED`]
+#[derive(Debug, Clone)]
+pub struct OcrFailure {
+    pub path: String,
+    pub message: String,
+}
+
+/// Result of an OCR job limited to a region, sent to the UI with [`SEND_REGION_OCR`]
+#[derive(Debug, Clone)]
+pub struct RegionOcr {
+    pub region: Rect,
+    pub ocr: Ocr,
+}
+
+/// Runs the OCR requests one at a time on a background thread
+///
+/// Every submission starts a new generation: jobs of older generations still
+/// waiting in the queue are dropped, and the result of a job that was running
+/// when a newer one arrived is discarded instead of being sent to the UI.
+///
+/// Results found in the cache are sent right away, without running the engine.
+/// Regions are never cached. The pages of a document are recognized one at a
+/// time, a newer job stops the remaining ones.
+pub struct OcrJobManager {
+    generation: Arc<AtomicU64>,
+    sender: Sender<OcrJob>,
+}
+
+impl OcrJobManager {
+    pub fn new(engine: Arc<dyn OcrEngine>, cache: Option<OcrCache>) -> Self {
+        let generation = Arc::new(AtomicU64::new(0));
+        let (sender, receiver) = mpsc::channel();
+        let worker_generation = generation.clone();
+        std::thread::Builder::new()
+            .name("ocr-jobs".to_string())
+            .spawn(move || run_jobs(engine, cache, worker_generation, receiver))
+            .expect("Failed to spawn the OCR thread");
+        Self { generation, sender }
+    }
+
+    /// Queue the OCR of page `page` of `path`, superseding every previous job
+    pub fn submit(&self, path: impl Into<String>, page: usize, options: OcrOptions, handle: ExtEventSink) -> u64 {
+        self.queue(path.into(), page..page + 1, options, None, false, handle)
+    }
+
+    /// Queue the OCR of the first `count` pages of `path`, superseding every previous job
+    pub fn submit_pages(&self, path: impl Into<String>, count: usize, options: OcrOptions, handle: ExtEventSink) -> u64 {
+        self.queue(path.into(), 0..count, options, None, false, handle)
+    }
+
+    /// Queue the OCR of a region of page `page` of `path`, superseding every previous job
+    pub fn submit_region(&self, path: impl Into<String>, page: usize, region: Rect, options: OcrOptions, handle: ExtEventSink) -> u64 {
+        self.queue(path.into(), page..page + 1, options, Some(region), false, handle)
+    }
+
+    /// Like [`submit`](Self::submit), but run the engine even if the result is cached
+    pub fn force(&self, path: impl Into<String>, page: usize, options: OcrOptions, handle: ExtEventSink) -> u64 {
+        self.queue(path.into(), page..page + 1, options, None, true, handle)
+    }
+
+    fn queue(&self, path: String, pages: Range<usize>, options: OcrOptions, region: Option<Rect>, force: bool, handle: ExtEventSink) -> u64 {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let job = OcrJob { generation, path, pages, options, region, force, handle };
+        if self.sender.send(job).is_err() {
+            eprintln!("The OCR thread is not running");
+        }
+        generation
+    }
+
+    /// Discard the results of every submitted job
+    pub fn cancel(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Generation of the last submitted or cancelled job
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::SeqCst)
+    }
+}
+
+fn run_jobs(engine: Arc<dyn OcrEngine>, cache: Option<OcrCache>, generation: Arc<AtomicU64>, receiver: Receiver<OcrJob>) {
+    let is_current = |job: &OcrJob| job.generation == generation.load(Ordering::SeqCst);
+    while let Ok(mut job) = receiver.recv() {
+        // Only the most recent request is worth running
+        while let Ok(newer) = receiver.try_recv() {
+            job = newer;
+        }
+        if !is_current(&job) {
+            continue;
+        }
+
+        if let Some(region) = job.region {
+            let result = recognize_region(engine.as_ref(), Path::new(&job.path), job.pages.start, region, &job.options);
+            if is_current(&job) {
+                send_result(&job, engine.as_ref(), result);
+            }
+            continue;
+        }
+
+        for page in job.pages.clone() {
+            let result = recognize_cached(engine.as_ref(), cache.as_ref(), &job.path, page, &job.options, job.force);
+            if !is_current(&job) {
+                break;
+            }
+            send_result(&job, engine.as_ref(), result);
+        }
+    }
+}
+
+/// Recognize page `page` of the image at `path`, reusing and filling the cache if there is one
+///
+/// With `force`, the cached result is dropped and the engine always runs.
+pub fn recognize_cached(
+    engine: &dyn OcrEngine,
+    cache: Option<&OcrCache>,
+    path: &str,
+    page: usize,
+    options: &OcrOptions,
+    force: bool,
+) -> Result<Ocr, OcrError> {
+    let cached = cache.and_then(|cache| {
+        let key = OcrCache::key(Path::new(path), engine, options)
+            .map_err(|e| eprintln!("Failed to read {} for the OCR cache: {}", path, e))
+            .ok()?;
+        // The pages of a document share the content of the file
+        let key = if page > 0 { format!("{}-{}", key, page) } else { key };
+        Some((cache, key))
+    });
+    if let Some((cache, key)) = &cached {
+        if force {
+            if let Err(e) = cache.remove(key) {
+                eprintln!("Failed to remove the cached OCR of {}: {}", path, e);
+            }
+        } else if let Some(mut ocr) = cache.get(key, path) {
+            ocr.page = page;
+            return Ok(ocr);
+        }
+    }
+
+    let result = recognize_page(engine, Path::new(path), page, options);
+    if let (Some((cache, key)), Ok(ocr)) = (&cached, &result) {
+        if let Err(e) = cache.put(key, ocr) {
+            eprintln!("Failed to cache the OCR of {}: {}", path, e);
+        }
+    }
+    result
+}
+
+/// Run the engine on page `page` of the image at `path`
+fn recognize_page(engine: &dyn OcrEngine, path: &Path, page: usize, options: &OcrOptions) -> Result<Ocr, OcrError> {
+    // Some engines read every page of a TIFF and none turns the photos upright,
+    // give them the page as it is shown
+    if page == 0 && page_count(path) == 1 && Metadata::read(path).orientation <= 1 {
+        return engine.recognize(path, options);
+    }
+    let image = open_page(path, page).map_err(|e| OcrError::Image(e.to_string()))?;
+    let image_size = Size::new(image.width() as f64, image.height() as f64);
+    let mut ocr = recognize_image(engine, &image, options)?;
+    ocr.img_path = path.to_string_lossy().into_owned();
+    ocr.page = page;
+    Ok(ocr.with_image_size(image_size))
+}
+
+fn send_result(job: &OcrJob, engine: &dyn OcrEngine, result: Result<Ocr, OcrError>) {
+    let sent = match (result, job.region) {
+        (Ok(ocr), Some(region)) => job.handle.submit_command(SEND_REGION_OCR, RegionOcr { region, ocr }, Target::Auto),
+        (Ok(ocr), None) => job.handle.submit_command(SEND_OCR, ocr, Target::Auto),
+        (Err(e), _) => {
+            let message = format!("OCR with {} failed: {} (engine {})", engine.name(), e, engine.health());
+            job.handle.submit_command(OCR_FAILED, OcrFailure { path: job.path.clone(), message }, Target::Auto)
+        },
+    };
+    if let Err(e) = sent {
+        eprintln!("Failed to send OCR result: {}", e);
+    }
+}
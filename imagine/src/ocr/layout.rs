@@ -0,0 +1,253 @@
+//! Layout analysis of the recognized boxes
+//!
+//! Engines emit their boxes in no particular order. The boxes are grouped into
+//! lines of vertically overlapping words, the lines are split where a wide gap
+//! separates two columns, and the lines are stacked into blocks. Blocks are then
+//! read row by row, from left to right or from right to left.
+
+use std::cmp::Ordering;
+
+use druid::Rect;
+
+use super::{Ocr, OcrTextBox};
+
+/// Part of the smallest height two boxes must share to be on the same line
+const LINE_OVERLAP: f64 = 0.5;
+/// Horizontal gap, in line heights, that splits a line in two
+const COLUMN_GAP: f64 = 3.0;
+/// Vertical gap, in line heights, up to which two lines are in the same block
+const BLOCK_GAP: f64 = 1.0;
+/// Height ratio above which two lines are not in the same block
+const MAX_HEIGHT_RATIO: f64 = 2.0;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ReadingDirection {
+    #[default]
+    LeftToRight,
+    RightToLeft,
+}
+
+impl ReadingDirection {
+    /// Languages written from right to left, with their easyocr and tesseract codes
+    const RTL_LANGUAGES: [&'static str; 10] = ["ar", "ara", "fa", "fas", "he", "heb", "ur", "urd", "ug", "uig"];
+
+    /// Right to left if any of the languages is
+    pub fn for_languages<'a>(languages: impl IntoIterator<Item = &'a String>) -> Self {
+        let rtl = languages.into_iter().any(|language| Self::RTL_LANGUAGES.contains(&language.as_str()));
+        if rtl { Self::RightToLeft } else { Self::LeftToRight }
+    }
+}
+
+/// Words sharing a baseline, in reading order
+#[derive(Debug, Clone)]
+pub struct OcrLine {
+    pub words: Vec<OcrTextBox>,
+    /// Bounding rect of the words, in image coordinates
+    pub rect: Rect,
+}
+
+impl OcrLine {
+    fn new(word: OcrTextBox) -> Self {
+        Self { rect: word.bounding_rect(), words: vec![word] }
+    }
+
+    fn push(&mut self, word: OcrTextBox) {
+        self.rect = self.rect.union(word.bounding_rect());
+        self.words.push(word);
+    }
+
+    pub fn text(&self) -> String {
+        self.words.iter().map(|word| word.text.as_str()).collect::<Vec<_>>().join(" ")
+    }
+
+    /// Sort the words and split the line where a gap separates two columns
+    fn split_columns(mut self, direction: ReadingDirection) -> Vec<OcrLine> {
+        self.words.sort_by(|a, b| compare_x(&a.bounding_rect(), &b.bounding_rect(), direction));
+        let max_gap = self.rect.height() * COLUMN_GAP;
+        let mut lines: Vec<OcrLine> = Vec::new();
+        for word in self.words {
+            let rect = word.bounding_rect();
+            match lines.last_mut() {
+                Some(line) if horizontal_gap(&line.rect, &rect) <= max_gap => line.push(word),
+                _ => lines.push(OcrLine::new(word)),
+            }
+        }
+        lines
+    }
+}
+
+/// Lines stacked on top of each other, such as a paragraph or a column
+#[derive(Debug, Clone)]
+pub struct OcrBlock {
+    pub lines: Vec<OcrLine>,
+    /// Bounding rect of the lines, in image coordinates
+    pub rect: Rect,
+}
+
+impl OcrBlock {
+    fn new(line: OcrLine) -> Self {
+        Self { rect: line.rect, lines: vec![line] }
+    }
+
+    /// Whether `line` continues the block, right under its last line
+    fn accepts(&self, line: &OcrLine) -> bool {
+        let last = self.lines.last().expect("blocks are never empty").rect;
+        let tall = last.height().max(line.rect.height());
+        let short = last.height().min(line.rect.height()).max(f64::EPSILON);
+        line.rect.y0 - last.y1 <= tall * BLOCK_GAP
+            && line.rect.y0 >= last.y0
+            && horizontal_gap(&self.rect, &line.rect) < 0.0
+            && tall / short <= MAX_HEIGHT_RATIO
+    }
+
+    fn push(&mut self, line: OcrLine) {
+        self.rect = self.rect.union(line.rect);
+        self.lines.push(line);
+    }
+
+    pub fn text(&self) -> String {
+        self.lines.iter().map(OcrLine::text).collect::<Vec<_>>().join("\n")
+    }
+}
+
+/// Group boxes into blocks of lines, in reading order
+pub fn analyze(boxes: impl IntoIterator<Item = OcrTextBox>, direction: ReadingDirection) -> Vec<OcrBlock> {
+    let mut boxes = boxes.into_iter().collect::<Vec<_>>();
+    boxes.sort_by(|a, b| a.bounding_rect().center().y.total_cmp(&b.bounding_rect().center().y));
+
+    // Words overlapping vertically share a line
+    let mut lines: Vec<OcrLine> = Vec::new();
+    for word in boxes {
+        let rect = word.bounding_rect();
+        let best = lines
+            .iter_mut()
+            .map(|line| (vertical_overlap(&line.rect, &rect), line))
+            .filter(|(overlap, _)| *overlap >= LINE_OVERLAP)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b));
+        match best {
+            Some((_, line)) => line.push(word),
+            None => lines.push(OcrLine::new(word)),
+        }
+    }
+    let mut lines = lines.into_iter().flat_map(|line| line.split_columns(direction)).collect::<Vec<_>>();
+    lines.sort_by(|a, b| a.rect.y0.total_cmp(&b.rect.y0));
+
+    // Lines right under each other share a block
+    let mut blocks: Vec<OcrBlock> = Vec::new();
+    for line in lines {
+        match blocks.iter_mut().rev().find(|block| block.accepts(&line)) {
+            Some(block) => block.push(line),
+            None => blocks.push(OcrBlock::new(line)),
+        }
+    }
+
+    // Blocks starting at about the same height form a row, read in the text direction
+    let mut rows: Vec<Vec<OcrBlock>> = Vec::new();
+    for block in blocks {
+        match rows.last_mut() {
+            Some(row) if block.rect.y0 - row[0].rect.y0 < row[0].lines[0].rect.height() / 2.0 => row.push(block),
+            _ => rows.push(vec![block]),
+        }
+    }
+    rows.into_iter()
+        .flat_map(|mut row| {
+            row.sort_by(|a, b| compare_x(&a.rect, &b.rect, direction));
+            row
+        })
+        .collect()
+}
+
+/// Order of two rects along the reading direction
+fn compare_x(a: &Rect, b: &Rect, direction: ReadingDirection) -> Ordering {
+    match direction {
+        ReadingDirection::LeftToRight => a.x0.total_cmp(&b.x0),
+        ReadingDirection::RightToLeft => b.x1.total_cmp(&a.x1),
+    }
+}
+
+/// Part of the smallest height shared by the two rects
+fn vertical_overlap(a: &Rect, b: &Rect) -> f64 {
+    let overlap = a.y1.min(b.y1) - a.y0.max(b.y0);
+    overlap / a.height().min(b.height()).max(f64::EPSILON)
+}
+
+/// Horizontal distance between the two rects, negative if they overlap
+fn horizontal_gap(a: &Rect, b: &Rect) -> f64 {
+    a.x0.max(b.x0) - a.x1.min(b.x1)
+}
+
+impl Ocr {
+    /// Direction of the recognized languages
+    pub fn reading_direction(&self) -> ReadingDirection {
+        ReadingDirection::for_languages(&self.languages)
+    }
+
+    /// Blocks of lines, in reading order
+    pub fn blocks(&self) -> Vec<OcrBlock> {
+        analyze(self.content.iter().cloned(), self.reading_direction())
+    }
+
+    /// Lines of every block, in reading order
+    pub fn lines(&self) -> Vec<OcrLine> {
+        self.blocks().into_iter().flat_map(|block| block.lines).collect()
+    }
+
+    /// The recognized text, one line per line and a blank line between blocks
+    pub fn full_text(&self) -> String {
+        self.blocks().iter().map(OcrBlock::text).collect::<Vec<_>>().join("\n\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ocr::tests::word;
+
+    fn ocr(content: Vec<OcrTextBox>) -> Ocr {
+        Ocr::new("image.png", content)
+    }
+
+    #[test]
+    fn orders_words_of_a_line() {
+        let ocr = ocr(vec![
+            word("world", 60.0, 12.0, 50.0, 20.0),
+            word("Hello", 0.0, 10.0, 50.0, 20.0),
+            word("again", 0.0, 40.0, 50.0, 20.0),
+        ]);
+        let lines = ocr.lines();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].text(), "Hello world");
+        assert_eq!(ocr.full_text(), "Hello world\nagain");
+    }
+
+    #[test]
+    fn reads_columns_one_after_the_other() {
+        let ocr = ocr(vec![
+            word("right1", 300.0, 0.0, 60.0, 20.0),
+            word("left1", 0.0, 0.0, 60.0, 20.0),
+            word("left2", 0.0, 25.0, 60.0, 20.0),
+            word("right2", 300.0, 25.0, 60.0, 20.0),
+        ]);
+        assert_eq!(ocr.full_text(), "left1\nleft2\n\nright1\nright2");
+    }
+
+    #[test]
+    fn separates_distant_blocks() {
+        let ocr = ocr(vec![
+            word("footer", 0.0, 200.0, 60.0, 20.0),
+            word("title", 0.0, 0.0, 60.0, 50.0),
+            word("body", 0.0, 60.0, 60.0, 20.0),
+        ]);
+        assert_eq!(ocr.full_text(), "title\n\nbody\n\nfooter");
+    }
+
+    #[test]
+    fn reads_right_to_left() {
+        let ocr = ocr(vec![
+            word("first", 100.0, 0.0, 50.0, 20.0),
+            word("second", 40.0, 0.0, 50.0, 20.0),
+        ]).with_languages(vec!["ar".to_string()]);
+        assert_eq!(ocr.reading_direction(), ReadingDirection::RightToLeft);
+        assert_eq!(ocr.full_text(), "first second");
+    }
+}
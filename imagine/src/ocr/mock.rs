@@ -0,0 +1,47 @@
+use std::path::Path;
+
+use druid::{Point, Size, im::Vector};
+
+use super::{engine::{OcrEngine, OcrError}, options::OcrOptions, Ocr, OcrTextBox};
+
+/// Number of lines returned by the mock engine
+const LINES: usize = 3;
+
+/// Deterministic engine that does not recognize anything
+///
+/// It returns a fixed set of lines spread over the image, which is enough to
+/// exercise the OCR overlay without python or tesseract installed.
+#[derive(Default)]
+pub struct MockEngine;
+
+impl OcrEngine for MockEngine {
+    fn name(&self) -> &str {
+        "mock"
+    }
+
+    fn recognize(&self, path: &Path, options: &OcrOptions) -> Result<Ocr, OcrError> {
+        let (width, height) = ::image::image_dimensions(path).map_err(|e| OcrError::Image(e.to_string()))?;
+        let (width, height) = (width as f64, height as f64);
+        let line_height = height / (2 * LINES + 1) as f64;
+
+        let content = (0..LINES).map(|i| {
+            let top = line_height * (2 * i + 1) as f64;
+            let (left, right, bottom) = (width * 0.1, width * 0.9, top + line_height);
+            OcrTextBox {
+                boxes: Vector::from(vec![
+                    Point::new(left, top),
+                    Point::new(right, top),
+                    Point::new(right, bottom),
+                    Point::new(left, bottom),
+                ]),
+                text: format!("Mock line {}", i + 1),
+                confidence: 1.0,
+            }
+        }).collect::<Vec<_>>();
+
+        Ok(Ocr::new(path.to_string_lossy(), content)
+            .with_engine(self.name())
+            .with_languages(options.languages.iter().cloned())
+            .with_image_size(Size::new(width, height)))
+    }
+}
@@ -0,0 +1,135 @@
+use std::{fmt, str::FromStr};
+
+use druid::{Data, Lens, im::Vector};
+
+/// A language the engines can be asked to recognize
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Language {
+    /// easyocr code, also used in the options and the cache keys
+    pub code: &'static str,
+    /// tesseract code of the same language
+    pub tesseract: &'static str,
+    pub name: &'static str,
+}
+
+/// Languages offered in the OCR menu
+pub const LANGUAGES: [Language; 12] = [
+    Language { code: "en", tesseract: "eng", name: "English" },
+    Language { code: "fr", tesseract: "fra", name: "French" },
+    Language { code: "de", tesseract: "deu", name: "German" },
+    Language { code: "es", tesseract: "spa", name: "Spanish" },
+    Language { code: "it", tesseract: "ita", name: "Italian" },
+    Language { code: "pt", tesseract: "por", name: "Portuguese" },
+    Language { code: "nl", tesseract: "nld", name: "Dutch" },
+    Language { code: "ru", tesseract: "rus", name: "Russian" },
+    Language { code: "ar", tesseract: "ara", name: "Arabic" },
+    Language { code: "ja", tesseract: "jpn", name: "Japanese" },
+    Language { code: "ko", tesseract: "kor", name: "Korean" },
+    Language { code: "ch_sim", tesseract: "chi_sim", name: "Chinese (simplified)" },
+];
+
+/// tesseract code of an easyocr language code, unknown codes are passed as is
+pub fn tesseract_code(code: &str) -> &str {
+    LANGUAGES.iter().find(|language| language.code == code).map(|language| language.tesseract).unwrap_or(code)
+}
+
+/// Hardware the engine runs on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Data)]
+pub enum OcrDevice {
+    #[default]
+    Cpu,
+    Gpu,
+}
+
+impl FromStr for OcrDevice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "cpu" => Ok(OcrDevice::Cpu),
+            "gpu" => Ok(OcrDevice::Gpu),
+            _ => Err(format!("unknown OCR device `{}` (expected cpu or gpu)", s)),
+        }
+    }
+}
+
+impl fmt::Display for OcrDevice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            OcrDevice::Cpu => "cpu",
+            OcrDevice::Gpu => "gpu",
+        })
+    }
+}
+
+/// Settings passed to the engine with every image
+#[derive(Debug, Clone, PartialEq, Data, Lens)]
+pub struct OcrOptions {
+    /// easyocr language codes, never empty
+    pub languages: Vector<String>,
+    pub device: OcrDevice,
+}
+
+impl Default for OcrOptions {
+    fn default() -> Self {
+        Self {
+            languages: Vector::from(vec!["fr".to_string(), "en".to_string()]),
+            device: OcrDevice::default(),
+        }
+    }
+}
+
+impl OcrOptions {
+    /// Parse a comma separated list of language codes
+    pub fn parse_languages(list: &str) -> Result<Vector<String>, String> {
+        let languages = list
+            .split(',')
+            .map(str::trim)
+            .filter(|code| !code.is_empty())
+            .map(str::to_string)
+            .collect::<Vector<_>>();
+        if languages.is_empty() {
+            return Err(format!("no OCR language in `{}`", list));
+        }
+        Ok(languages)
+    }
+
+    /// Add or remove a language, the last one is kept
+    pub fn toggle_language(&mut self, code: &str) {
+        match self.languages.index_of(&code.to_string()) {
+            Some(_) if self.languages.len() == 1 => {},
+            Some(index) => {
+                self.languages.remove(index);
+            },
+            None => self.languages.push_back(code.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_language_lists() {
+        assert_eq!(OcrOptions::parse_languages(" de, en ,").unwrap(), Vector::from(vec!["de".to_string(), "en".to_string()]));
+        assert!(OcrOptions::parse_languages(" , ").is_err());
+    }
+
+    #[test]
+    fn keeps_the_last_language() {
+        let mut options = OcrOptions::default();
+        options.toggle_language("ja");
+        assert_eq!(options.languages.len(), 3);
+        options.toggle_language("fr");
+        options.toggle_language("en");
+        options.toggle_language("ja");
+        assert_eq!(options.languages, Vector::from(vec!["ja".to_string()]));
+    }
+
+    #[test]
+    fn maps_tesseract_codes() {
+        assert_eq!(tesseract_code("de"), "deu");
+        assert_eq!(tesseract_code("custom"), "custom");
+    }
+}
@@ -0,0 +1,298 @@
+use std::fmt;
+
+use druid::{Data, Point, im::Vector};
+
+use super::OcrTextBox;
+
+/// Number of points of an OCR polygon
+const POLYGON_POINTS: usize = 4;
+
+/// Error raised when a line of OCR output can not be understood
+#[derive(Debug, Clone, PartialEq, Data)]
+pub enum OcrParseError {
+    /// The line does not have the `points;text;confidence` shape
+    MissingField(&'static str),
+    /// The polygon is not a list of `[x, y]` points
+    InvalidPoints(String),
+    /// A point does not have exactly two coordinates
+    InvalidPoint(String),
+    /// A coordinate is not a finite number
+    InvalidCoordinate(String),
+    /// The polygon does not have four points
+    WrongPointCount(usize),
+    /// The confidence is not a number between 0 and 1
+    InvalidConfidence(String),
+    /// The JSON document does not follow the schema
+    InvalidJson(String),
+    /// The JSON document was written for another version of the schema
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for OcrParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OcrParseError::MissingField(field) => write!(f, "missing {}", field),
+            OcrParseError::InvalidPoints(points) => write!(f, "invalid polygon `{}`", points),
+            OcrParseError::InvalidPoint(point) => write!(f, "invalid point `{}`", point),
+            OcrParseError::InvalidCoordinate(coordinate) => write!(f, "invalid coordinate `{}`", coordinate),
+            OcrParseError::WrongPointCount(count) => write!(f, "expected {} points, found {}", POLYGON_POINTS, count),
+            OcrParseError::InvalidConfidence(confidence) => write!(f, "invalid confidence `{}`", confidence),
+            OcrParseError::InvalidJson(e) => write!(f, "invalid JSON document: {}", e),
+            OcrParseError::UnsupportedVersion(version) => write!(f, "unsupported protocol version {}", version),
+        }
+    }
+}
+
+impl std::error::Error for OcrParseError {}
+
+/// An entry of the engine output that was skipped
+#[derive(Debug, Clone, PartialEq, Data)]
+pub struct OcrDiagnostic {
+    /// Line number in the legacy format, box number in the JSON one, starting at 1
+    pub entry: usize,
+    pub error: OcrParseError,
+}
+
+impl fmt::Display for OcrDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "entry {}: {}", self.entry, self.error)
+    }
+}
+
+/// Result of parsing a whole engine output: every valid line is kept
+#[derive(Debug, Clone, Default)]
+pub struct ParsedOcr {
+    pub content: Vec<OcrTextBox>,
+    pub diagnostics: Vec<OcrDiagnostic>,
+}
+
+/// Parse every non empty line of the engine output
+pub fn parse_ocr_output(output: &str) -> ParsedOcr {
+    let mut parsed = ParsedOcr::default();
+    for (i, line) in output.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match OcrTextBox::parse_ocr_text_box(line) {
+            Ok(text_box) => parsed.content.push(text_box),
+            Err(error) => parsed.diagnostics.push(OcrDiagnostic { entry: i + 1, error }),
+        }
+    }
+    parsed
+}
+
+impl OcrTextBox {
+    /// Parse a `([x, y]-[x, y]-[x, y]-[x, y]);text;confidence` line
+    ///
+    /// The text may itself contain `;`: the polygon ends at the first one and
+    /// the confidence starts after the last one.
+    pub fn parse_ocr_text_box(input: &str) -> Result<OcrTextBox, OcrParseError> {
+        let (points, rest) = input.split_once(';').ok_or(OcrParseError::MissingField("text"))?;
+        let (text, confidence) = rest.rsplit_once(';').ok_or(OcrParseError::MissingField("confidence"))?;
+
+        let boxes = validate_polygon(parse_points(points.trim())?)?;
+
+        let confidence = confidence.trim();
+        let value = strip_numpy_scalar(confidence)
+            .parse::<f64>()
+            .map_err(|_| OcrParseError::InvalidConfidence(confidence.to_string()))?;
+        let confidence = validate_confidence(value, confidence)?;
+
+        Ok(Self {
+            boxes,
+            text: text.to_string(),
+            confidence,
+        })
+    }
+}
+
+/// Check that a polygon has four points with finite coordinates
+pub fn validate_polygon(points: Vector<Point>) -> Result<Vector<Point>, OcrParseError> {
+    if points.len() != POLYGON_POINTS {
+        return Err(OcrParseError::WrongPointCount(points.len()));
+    }
+    match points.iter().find(|point| !point.is_finite()) {
+        Some(point) => Err(OcrParseError::InvalidPoint(format!("{}, {}", point.x, point.y))),
+        None => Ok(points),
+    }
+}
+
+/// Check that a confidence is between 0 and 1, `raw` is its original text
+pub fn validate_confidence(confidence: f64, raw: &str) -> Result<f64, OcrParseError> {
+    if (0.0..=1.0).contains(&confidence) {
+        Ok(confidence)
+    } else {
+        Err(OcrParseError::InvalidConfidence(raw.to_string()))
+    }
+}
+
+/// Parse a `([x, y]-[x, y]-...)` polygon
+///
+/// Points are read between brackets rather than split on `-`, so negative
+/// coordinates and exponents are supported.
+fn parse_points(input: &str) -> Result<Vector<Point>, OcrParseError> {
+    let inner = input
+        .strip_prefix('(')
+        .and_then(|input| input.strip_suffix(')'))
+        .ok_or_else(|| OcrParseError::InvalidPoints(input.to_string()))?;
+
+    let mut points = Vector::new();
+    let mut rest = inner.trim();
+    while !rest.is_empty() {
+        let start = rest.strip_prefix('[').ok_or_else(|| OcrParseError::InvalidPoints(input.to_string()))?;
+        let end = start.find(']').ok_or_else(|| OcrParseError::InvalidPoints(input.to_string()))?;
+        points.push_back(parse_point(&start[..end])?);
+
+        rest = start[end + 1..].trim_start();
+        if let Some(next) = rest.strip_prefix('-') {
+            rest = next.trim_start();
+            if rest.is_empty() {
+                return Err(OcrParseError::InvalidPoints(input.to_string()));
+            }
+        } else if !rest.is_empty() {
+            return Err(OcrParseError::InvalidPoints(input.to_string()));
+        }
+    }
+    Ok(points)
+}
+
+/// Parse the `x, y` content of a point
+fn parse_point(input: &str) -> Result<Point, OcrParseError> {
+    let coordinates = input.split(',').map(parse_coordinate).collect::<Result<Vec<_>, _>>()?;
+    match coordinates[..] {
+        [x, y] => Ok(Point::new(x, y)),
+        _ => Err(OcrParseError::InvalidPoint(input.to_string())),
+    }
+}
+
+/// Parse a single coordinate
+fn parse_coordinate(input: &str) -> Result<f64, OcrParseError> {
+    strip_numpy_scalar(input.trim())
+        .parse::<f64>()
+        .ok()
+        .filter(|coordinate| coordinate.is_finite())
+        .ok_or_else(|| OcrParseError::InvalidCoordinate(input.trim().to_string()))
+}
+
+/// Numpy 2 prints its scalars as `np.int32(12)`: keep only the value
+fn strip_numpy_scalar(input: &str) -> &str {
+    match input.split_once('(') {
+        Some((_, value)) => value.strip_suffix(')').unwrap_or(value).trim(),
+        None => input,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(text_box: &OcrTextBox) -> Vec<(f64, f64)> {
+        text_box.boxes.iter().map(|point| (point.x, point.y)).collect()
+    }
+
+    #[test]
+    fn parses_integer_coordinates() {
+        let text_box = OcrTextBox::parse_ocr_text_box("([53, 53]-[105, 53]-[105, 67]-[53, 67]);Il Pause;0.87").unwrap();
+        assert_eq!(points(&text_box), vec![(53.0, 53.0), (105.0, 53.0), (105.0, 67.0), (53.0, 67.0)]);
+        assert_eq!(text_box.text, "Il Pause");
+        assert_eq!(text_box.confidence, 0.87);
+    }
+
+    #[test]
+    fn parses_float_coordinates_of_rotated_boxes() {
+        let line = "([2.585786437626905, 256.5857864376269]-[42.778575604636295, 253.0853039747585]-\
+                    [44.41421356237309, 269.4142135623731]-[4.221424395363706, 272.91469602524154]);0.0096;0.5";
+        let text_box = OcrTextBox::parse_ocr_text_box(line).unwrap();
+        assert_eq!(text_box.boxes[0], Point::new(2.585786437626905, 256.5857864376269));
+        assert_eq!(text_box.boxes[3], Point::new(4.221424395363706, 272.91469602524154));
+        assert_eq!(text_box.text, "0.0096");
+    }
+
+    #[test]
+    fn parses_negative_and_exponent_coordinates() {
+        let text_box = OcrTextBox::parse_ocr_text_box("([-1.5, -2]-[1e1, -2]-[1e1, 3.5e0]-[-1.5, 3.5]);x;1").unwrap();
+        assert_eq!(points(&text_box), vec![(-1.5, -2.0), (10.0, -2.0), (10.0, 3.5), (-1.5, 3.5)]);
+    }
+
+    #[test]
+    fn parses_numpy_scalars() {
+        let line = "([np.int32(1), np.int32(2)]-[np.int32(3), np.int32(2)]-[np.int32(3), np.int32(4)]-[np.int32(1), np.int32(4)]);a;np.float64(0.5)";
+        let text_box = OcrTextBox::parse_ocr_text_box(line).unwrap();
+        assert_eq!(points(&text_box), vec![(1.0, 2.0), (3.0, 2.0), (3.0, 4.0), (1.0, 4.0)]);
+        assert_eq!(text_box.confidence, 0.5);
+    }
+
+    #[test]
+    fn keeps_semicolons_in_text() {
+        let text_box = OcrTextBox::parse_ocr_text_box("([0, 0]-[1, 0]-[1, 1]-[0, 1]);bytes visible data points; 0);0.25").unwrap();
+        assert_eq!(text_box.text, "bytes visible data points; 0)");
+        assert_eq!(text_box.confidence, 0.25);
+    }
+
+    #[test]
+    fn keeps_empty_text() {
+        let text_box = OcrTextBox::parse_ocr_text_box("([0, 0]-[1, 0]-[1, 1]-[0, 1]);;1.0").unwrap();
+        assert_eq!(text_box.text, "");
+    }
+
+    #[test]
+    fn rejects_missing_fields() {
+        assert_eq!(OcrTextBox::parse_ocr_text_box("([0, 0]-[1, 0]-[1, 1]-[0, 1])").unwrap_err(), OcrParseError::MissingField("text"));
+        assert_eq!(OcrTextBox::parse_ocr_text_box("([0, 0]-[1, 0]-[1, 1]-[0, 1]);text").unwrap_err(), OcrParseError::MissingField("confidence"));
+    }
+
+    #[test]
+    fn rejects_invalid_polygons() {
+        assert!(matches!(OcrTextBox::parse_ocr_text_box("[0, 0]-[1, 0]-[1, 1]-[0, 1];a;1"), Err(OcrParseError::InvalidPoints(_))));
+        assert!(matches!(OcrTextBox::parse_ocr_text_box("([0, 0]-[1, 0]-[1, 1]-);a;1"), Err(OcrParseError::InvalidPoints(_))));
+        assert!(matches!(OcrTextBox::parse_ocr_text_box("([0, 0] [1, 0]);a;1"), Err(OcrParseError::InvalidPoints(_))));
+        assert!(matches!(OcrTextBox::parse_ocr_text_box("([0, 0]-[1, 0]-[1, 1]-[0, 1);a;1"), Err(OcrParseError::InvalidPoints(_))));
+    }
+
+    #[test]
+    fn rejects_invalid_points() {
+        assert_eq!(
+            OcrTextBox::parse_ocr_text_box("([0, 0, 0]-[1, 0]-[1, 1]-[0, 1]);a;1").unwrap_err(),
+            OcrParseError::InvalidPoint("0, 0, 0".to_string())
+        );
+        assert_eq!(
+            OcrTextBox::parse_ocr_text_box("([0, x]-[1, 0]-[1, 1]-[0, 1]);a;1").unwrap_err(),
+            OcrParseError::InvalidCoordinate("x".to_string())
+        );
+        assert_eq!(
+            OcrTextBox::parse_ocr_text_box("([0, nan]-[1, 0]-[1, 1]-[0, 1]);a;1").unwrap_err(),
+            OcrParseError::InvalidCoordinate("nan".to_string())
+        );
+        assert_eq!(
+            OcrTextBox::parse_ocr_text_box("([0, 0]-[1, 0]-[1, 1]);a;1").unwrap_err(),
+            OcrParseError::WrongPointCount(3)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_confidence() {
+        assert_eq!(
+            OcrTextBox::parse_ocr_text_box("([0, 0]-[1, 0]-[1, 1]-[0, 1]);a;high").unwrap_err(),
+            OcrParseError::InvalidConfidence("high".to_string())
+        );
+        assert_eq!(
+            OcrTextBox::parse_ocr_text_box("([0, 0]-[1, 0]-[1, 1]-[0, 1]);a;1.5").unwrap_err(),
+            OcrParseError::InvalidConfidence("1.5".to_string())
+        );
+    }
+
+    #[test]
+    fn returns_partial_results_with_diagnostics() {
+        let output = "([0, 0]-[1, 0]-[1, 1]-[0, 1]);first;0.9\n\
+                      garbage\n\
+                      \n\
+                      ([0, 2]-[1, 2]-[1, 3]-[0, 3]);second;0.8\n\
+                      ([0, 4]-[1, 4]-[1, 5]);third;0.7\n";
+        let parsed = parse_ocr_output(output);
+        assert_eq!(parsed.content.iter().map(|text_box| text_box.text.as_str()).collect::<Vec<_>>(), vec!["first", "second"]);
+        assert_eq!(parsed.diagnostics, vec![
+            OcrDiagnostic { entry: 2, error: OcrParseError::MissingField("text") },
+            OcrDiagnostic { entry: 5, error: OcrParseError::WrongPointCount(3) },
+        ]);
+    }
+}
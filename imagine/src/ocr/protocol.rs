@@ -0,0 +1,251 @@
+//! JSON schema exchanged with the OCR helpers
+//!
+//! A helper prints a single document:
+//!
+//! ```json
+//! {
+//!   "version": 1,
+//!   "engine": "easyocr",
+//!   "languages": ["fr", "en"],
+//!   "image": { "width": 1920, "height": 1080 },
+//!   "boxes": [
+//!     { "polygon": [[621, 17], [1039, 17], [1039, 37], [621, 37]], "text": "Tracy Profiler", "confidence": 0.93 }
+//!   ]
+//! }
+//! ```
+//!
+//! Output that is not a JSON object is decoded with the legacy
+//! `(p1-p2-p3-p4);text;confidence` line format.
+//!
+//! A persistent worker reads one request per line on its stdin and answers
+//! with one response per line on its stdout, matched by `id`:
+//!
+//! ```json
+//! {"id": 1, "method": "ocr", "path": "/tmp/screenshot.png", "languages": ["fr", "en"], "gpu": false}
+//! {"id": 1, "result": { "version": 1, "engine": "easyocr", ... }}
+//! {"id": 2, "method": "health"}
+//! {"id": 2, "result": { "status": "ready", "engine": "easyocr", "languages": ["fr", "en"] }}
+//! {"id": 3, "error": "cannot identify image file"}
+//! ```
+
+use druid::{Point, Size, im::Vector};
+use serde::{Deserialize, Serialize};
+
+use super::{engine::OcrError, parser::{parse_ocr_output, validate_polygon, validate_confidence, OcrDiagnostic, OcrParseError, ParsedOcr}, Ocr, OcrTextBox};
+
+/// Version of the schema written by this build
+pub const PROTOCOL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OcrDocument {
+    pub version: u32,
+    pub engine: String,
+    #[serde(default)]
+    pub languages: Vec<String>,
+    pub image: OcrImage,
+    pub boxes: Vec<OcrBox>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct OcrImage {
+    pub width: u32,
+    pub height: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OcrBox {
+    /// Corners of the box, clockwise from the top left one
+    pub polygon: Vec<[f64; 2]>,
+    pub text: String,
+    pub confidence: f64,
+}
+
+/// Request sent to a persistent worker
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerRequest {
+    pub id: u64,
+    #[serde(flatten)]
+    pub method: WorkerMethod,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "method", rename_all = "lowercase")]
+pub enum WorkerMethod {
+    /// Recognize the text of an image, answered with an [`OcrDocument`]
+    Ocr {
+        path: String,
+        /// easyocr language codes, the worker default if empty
+        #[serde(default)]
+        languages: Vec<String>,
+        #[serde(default)]
+        gpu: bool,
+    },
+    /// Report the state of the worker, answered with a [`WorkerHealth`]
+    Health,
+}
+
+/// Response of a persistent worker, `id` is missing if the request was unreadable
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerResponse {
+    pub id: Option<u64>,
+    #[serde(default)]
+    pub result: Option<serde_json::Value>,
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+impl WorkerResponse {
+    pub fn into_result(self) -> Result<serde_json::Value, OcrError> {
+        match (self.result, self.error) {
+            (_, Some(error)) => Err(OcrError::Worker(error)),
+            (Some(result), None) => Ok(result),
+            (None, None) => Err(OcrError::Worker("empty response".to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WorkerHealth {
+    /// `idle` until the model is loaded, then `ready`
+    pub status: String,
+    pub engine: String,
+    #[serde(default)]
+    pub languages: Vec<String>,
+}
+
+impl OcrDocument {
+    /// Convert the document to OCR results, skipping the invalid boxes
+    pub fn into_ocr(self, img_path: impl Into<String>) -> Result<Ocr, OcrParseError> {
+        if self.version != PROTOCOL_VERSION {
+            return Err(OcrParseError::UnsupportedVersion(self.version));
+        }
+
+        let mut parsed = ParsedOcr::default();
+        for (i, ocr_box) in self.boxes.into_iter().enumerate() {
+            let points = ocr_box.polygon.iter().map(|[x, y]| Point::new(*x, *y)).collect::<Vector<_>>();
+            let text_box = validate_polygon(points).and_then(|boxes| {
+                Ok(OcrTextBox {
+                    boxes,
+                    text: ocr_box.text,
+                    confidence: validate_confidence(ocr_box.confidence, &ocr_box.confidence.to_string())?,
+                })
+            });
+            match text_box {
+                Ok(text_box) => parsed.content.push(text_box),
+                Err(error) => parsed.diagnostics.push(OcrDiagnostic { entry: i + 1, error }),
+            }
+        }
+
+        Ok(Ocr::from_parsed(img_path, parsed)
+            .with_engine(self.engine)
+            .with_languages(self.languages)
+            .with_image_size(Size::new(self.image.width as f64, self.image.height as f64)))
+    }
+}
+
+impl From<&Ocr> for OcrDocument {
+    fn from(ocr: &Ocr) -> Self {
+        Self {
+            version: PROTOCOL_VERSION,
+            engine: ocr.engine.clone(),
+            languages: ocr.languages.iter().cloned().collect(),
+            image: OcrImage {
+                width: ocr.image_size.width as u32,
+                height: ocr.image_size.height as u32,
+            },
+            boxes: ocr.content.iter().map(|text_box| OcrBox {
+                polygon: text_box.boxes.iter().map(|point| [point.x, point.y]).collect(),
+                text: text_box.text.clone(),
+                confidence: text_box.confidence,
+            }).collect(),
+        }
+    }
+}
+
+/// Decode the output of an OCR helper, in the JSON or in the legacy format
+pub fn decode_ocr_output(output: &str, img_path: impl Into<String>) -> Result<Ocr, OcrParseError> {
+    if output.trim_start().starts_with('{') {
+        let document: OcrDocument = serde_json::from_str(output).map_err(|e| OcrParseError::InvalidJson(e.to_string()))?;
+        document.into_ocr(img_path)
+    } else {
+        Ok(Ocr::from_parsed(img_path, parse_ocr_output(output)))
+    }
+}
+
+impl Ocr {
+    /// Serialize the results with the JSON schema
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&OcrDocument::from(self)).expect("OCR documents are always serializable")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DOCUMENT: &str = r#"{
+        "version": 1,
+        "engine": "easyocr",
+        "languages": ["fr", "en"],
+        "image": { "width": 1920, "height": 1080 },
+        "boxes": [
+            { "polygon": [[53, 53], [105, 53], [105, 67], [53, 67]], "text": "Il Pause", "confidence": 0.87 },
+            { "polygon": [[0, 0], [1, 0], [1, 1]], "text": "triangle", "confidence": 0.5 },
+            { "polygon": [[2.5, 256.5], [42.7, 253.0], [44.4, 269.4], [4.2, 272.9]], "text": "a;b", "confidence": 0.25 }
+        ]
+    }"#;
+
+    #[test]
+    fn decodes_json_documents() {
+        let ocr = decode_ocr_output(DOCUMENT, "image.png").unwrap();
+        assert_eq!(ocr.engine, "easyocr");
+        assert_eq!(ocr.languages, Vector::from(vec!["fr".to_string(), "en".to_string()]));
+        assert_eq!(ocr.image_size, Size::new(1920.0, 1080.0));
+        assert_eq!(ocr.content.len(), 2);
+        assert_eq!(ocr.content[1].text, "a;b");
+        assert_eq!(ocr.content[1].boxes[0], Point::new(2.5, 256.5));
+        assert_eq!(ocr.diagnostics, Vector::from(vec![OcrDiagnostic { entry: 2, error: OcrParseError::WrongPointCount(3) }]));
+    }
+
+    #[test]
+    fn falls_back_to_legacy_lines() {
+        let ocr = decode_ocr_output("([0, 0]-[1, 0]-[1, 1]-[0, 1]);legacy;0.5\n", "image.png").unwrap();
+        assert_eq!(ocr.content.len(), 1);
+        assert_eq!(ocr.content[0].text, "legacy");
+    }
+
+    #[test]
+    fn rejects_invalid_documents() {
+        assert!(matches!(decode_ocr_output("{ \"version\": 1 }", "image.png"), Err(OcrParseError::InvalidJson(_))));
+        let document = DOCUMENT.replace("\"version\": 1", "\"version\": 2");
+        assert_eq!(decode_ocr_output(&document, "image.png").err(), Some(OcrParseError::UnsupportedVersion(2)));
+    }
+
+    #[test]
+    fn encodes_worker_messages() {
+        let request = WorkerRequest {
+            id: 7,
+            method: WorkerMethod::Ocr { path: "a.png".to_string(), languages: vec!["de".to_string()], gpu: true },
+        };
+        assert_eq!(serde_json::to_string(&request).unwrap(), r#"{"id":7,"method":"ocr","path":"a.png","languages":["de"],"gpu":true}"#);
+        let request = WorkerRequest { id: 8, method: WorkerMethod::Health };
+        assert_eq!(serde_json::to_string(&request).unwrap(), r#"{"id":8,"method":"health"}"#);
+
+        let response: WorkerResponse = serde_json::from_str(r#"{"id": 7, "error": "boom"}"#).unwrap();
+        assert_eq!(response.id, Some(7));
+        assert!(matches!(response.into_result(), Err(OcrError::Worker(e)) if e == "boom"));
+        let response: WorkerResponse = serde_json::from_str(r#"{"id": 8, "result": {"status": "idle", "engine": "easyocr"}}"#).unwrap();
+        let health: WorkerHealth = serde_json::from_value(response.into_result().unwrap()).unwrap();
+        assert_eq!(health.status, "idle");
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let ocr = decode_ocr_output(DOCUMENT, "image.png").unwrap();
+        let decoded = decode_ocr_output(&ocr.to_json(), "image.png").unwrap();
+        assert_eq!(decoded.content.len(), ocr.content.len());
+        assert_eq!(decoded.content[0].boxes, ocr.content[0].boxes);
+        assert_eq!(decoded.engine, ocr.engine);
+        assert_eq!(decoded.image_size, ocr.image_size);
+    }
+}
@@ -0,0 +1,111 @@
+use std::{
+    fs,
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use ::image::DynamicImage;
+use druid::{Rect, Size, Vec2};
+
+use crate::pages::open_page;
+
+use super::{engine::{OcrEngine, OcrError}, options::OcrOptions, Ocr};
+
+/// Number of temporary images written so far, to give each its own file
+static CROPS: AtomicU64 = AtomicU64::new(0);
+
+/// Recognize the text of a region of page `page` of the image at `path`
+///
+/// The region is cropped to a temporary file, which the engines can read like
+/// any other image, and the boxes are moved back to the coordinates of the
+/// whole image.
+pub fn recognize_region(engine: &dyn OcrEngine, path: &Path, page: usize, region: Rect, options: &OcrOptions) -> Result<Ocr, OcrError> {
+    let image = open_page(path, page).map_err(|e| OcrError::Image(e.to_string()))?;
+    let image_size = Size::new(image.width() as f64, image.height() as f64);
+    let region = region.intersect(image_size.to_rect()).round();
+    if region.width() < 1.0 || region.height() < 1.0 {
+        return Err(OcrError::Image("the selected region is outside of the image".to_string()));
+    }
+
+    let crop = image.crop_imm(region.x0 as u32, region.y0 as u32, region.width() as u32, region.height() as u32);
+    let mut ocr = recognize_image(engine, &crop, options)?;
+    ocr.translate(region.origin().to_vec2());
+    ocr.img_path = path.to_string_lossy().into_owned();
+    ocr.page = page;
+    Ok(ocr.with_image_size(image_size))
+}
+
+/// Recognize the text of a decoded image, such as a page of a TIFF
+///
+/// The engines only read files, the image is written to a temporary one.
+pub fn recognize_image(engine: &dyn OcrEngine, image: &DynamicImage, options: &OcrOptions) -> Result<Ocr, OcrError> {
+    let image_path = std::env::temp_dir().join(format!(
+        "imagine-region-{}-{}.png",
+        std::process::id(),
+        CROPS.fetch_add(1, Ordering::Relaxed),
+    ));
+    image.save(&image_path).map_err(|e| OcrError::Image(e.to_string()))?;
+    let result = engine.recognize(&image_path, options);
+    let _ = fs::remove_file(&image_path);
+    result
+}
+
+impl Ocr {
+    /// Move every box by `offset`
+    pub fn translate(&mut self, offset: Vec2) {
+        for text_box in self.content.iter_mut() {
+            for point in text_box.boxes.iter_mut() {
+                *point += offset;
+            }
+        }
+    }
+
+    /// Replace the boxes centered in `region` by the results of a region OCR
+    pub fn merge_region(&mut self, region: Rect, region_ocr: Ocr) {
+        self.content.retain(|text_box| !region.contains(text_box.bounding_rect().center()));
+        self.content.append(region_ocr.content);
+        self.diagnostics.append(region_ocr.diagnostics);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ocr::{mock::MockEngine, tests::word};
+
+    #[test]
+    fn maps_boxes_back_to_the_image() {
+        let path = std::env::temp_dir().join(format!("imagine-region-test-{}.png", std::process::id()));
+        ::image::RgbImage::new(200, 100).save(&path).unwrap();
+        let region = Rect::new(100.0, 50.0, 300.0, 90.0);
+        let ocr = recognize_region(&MockEngine, &path, 0, region, &OcrOptions::default()).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(ocr.image_size, Size::new(200.0, 100.0));
+        assert_eq!(ocr.img_path, path.to_string_lossy());
+        // The crop is 100x40, the mock lines start at 10% of its width
+        assert_eq!(ocr.content[0].bounding_rect().x0, 110.0);
+        assert!(ocr.content.iter().all(|text_box| Rect::new(100.0, 50.0, 200.0, 90.0).contains(text_box.bounding_rect().center())));
+    }
+
+    #[test]
+    fn rejects_regions_outside_of_the_image() {
+        let path = std::env::temp_dir().join(format!("imagine-region-outside-{}.png", std::process::id()));
+        ::image::RgbImage::new(20, 10).save(&path).unwrap();
+        let result = recognize_region(&MockEngine, &path, 0, Rect::new(30.0, 0.0, 40.0, 10.0), &OcrOptions::default());
+        fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(OcrError::Image(_))));
+    }
+
+    #[test]
+    fn replaces_boxes_in_the_region() {
+        let mut ocr = Ocr::new("image.png", vec![
+            word("outside", 0.0, 0.0, 10.0, 10.0),
+            word("inside", 50.0, 50.0, 10.0, 10.0),
+        ]);
+        let region_ocr = Ocr::new("image.png", vec![word("new", 52.0, 52.0, 6.0, 6.0)]);
+        ocr.merge_region(Rect::new(40.0, 40.0, 100.0, 100.0), region_ocr);
+        let texts = ocr.content.iter().map(|text_box| text_box.text.as_str()).collect::<Vec<_>>();
+        assert_eq!(texts, vec!["outside", "new"]);
+    }
+}
@@ -0,0 +1,106 @@
+use std::{path::Path, process::Command};
+
+use druid::{Point, Size, im::Vector};
+
+use super::{engine::{OcrEngine, OcrError}, options::{tesseract_code, OcrOptions}, Ocr, OcrTextBox};
+
+/// Tesseract level of a recognized word in its TSV output
+const WORD_LEVEL: &str = "5";
+
+/// Runs the `tesseract` command line tool and reads its TSV output
+pub struct TesseractEngine {
+    program: String,
+}
+
+impl TesseractEngine {
+    pub fn new(program: impl Into<String>) -> Self {
+        Self { program: program.into() }
+    }
+
+    /// Convert one word row of the TSV output to a text box
+    ///
+    /// Columns are: level, page_num, block_num, par_num, line_num, word_num,
+    /// left, top, width, height, conf, text.
+    fn parse_tsv_row(row: &str) -> Option<OcrTextBox> {
+        let columns: Vec<&str> = row.splitn(12, '\t').collect();
+        if columns.len() != 12 || columns[0] != WORD_LEVEL {
+            return None;
+        }
+        let text = columns[11].trim();
+        let confidence = columns[10].parse::<f64>().ok()?;
+        if text.is_empty() || confidence < 0.0 {
+            return None;
+        }
+        let left = columns[6].parse::<f64>().ok()?;
+        let top = columns[7].parse::<f64>().ok()?;
+        let right = left + columns[8].parse::<f64>().ok()?;
+        let bottom = top + columns[9].parse::<f64>().ok()?;
+
+        Some(OcrTextBox {
+            boxes: Vector::from(vec![
+                Point::new(left, top),
+                Point::new(right, top),
+                Point::new(right, bottom),
+                Point::new(left, bottom),
+            ]),
+            text: text.to_string(),
+            confidence: confidence / 100.0,
+        })
+    }
+}
+
+impl OcrEngine for TesseractEngine {
+    fn name(&self) -> &str {
+        "tesseract"
+    }
+
+    /// The device is ignored, tesseract only runs on the CPU
+    fn recognize(&self, path: &Path, options: &OcrOptions) -> Result<Ocr, OcrError> {
+        let path = path.to_str().ok_or(OcrError::InvalidPath)?;
+        let (width, height) = ::image::image_dimensions(path).map_err(|e| OcrError::Image(e.to_string()))?;
+        let output = Command::new(&self.program)
+            .arg(path)
+            .arg("stdout")
+            .arg("-l")
+            .arg(options.languages.iter().map(|code| tesseract_code(code)).collect::<Vec<_>>().join("+"))
+            .arg("tsv")
+            .output()
+            .map_err(|source| OcrError::Spawn { program: self.program.clone(), source })?;
+        if !output.status.success() {
+            return Err(OcrError::Failed {
+                program: self.program.clone(),
+                status: output.status,
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let content = stdout.lines().skip(1).filter_map(Self::parse_tsv_row).collect::<Vec<_>>();
+        Ok(Ocr::new(path, content)
+            .with_engine(self.name())
+            .with_languages(options.languages.iter().cloned())
+            .with_image_size(Size::new(width as f64, height as f64)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_word_rows() {
+        let text_box = TesseractEngine::parse_tsv_row("5\t1\t1\t1\t1\t1\t10\t20\t30\t40\t96.5\tHello world").unwrap();
+        assert_eq!(text_box.text, "Hello world");
+        assert_eq!(text_box.confidence, 0.965);
+        assert_eq!(text_box.boxes[0], Point::new(10.0, 20.0));
+        assert_eq!(text_box.boxes[2], Point::new(40.0, 60.0));
+    }
+
+    #[test]
+    fn skips_other_rows() {
+        assert!(TesseractEngine::parse_tsv_row("4\t1\t1\t1\t1\t0\t10\t20\t30\t40\t-1\t").is_none());
+        assert!(TesseractEngine::parse_tsv_row("5\t1\t1\t1\t1\t1\t10\t20\t30\t40\t-1\t ").is_none());
+        assert!(TesseractEngine::parse_tsv_row("5\t1\t1\t1\t1\t1\tx\t20\t30\t40\t90\tword").is_none());
+        assert!(TesseractEngine::parse_tsv_row("garbage").is_none());
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    fmt,
+    io::{self, BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::{atomic::{AtomicU64, Ordering}, mpsc::{self, Receiver, RecvTimeoutError}, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::{engine::OcrError, protocol::{WorkerHealth, WorkerMethod, WorkerRequest, WorkerResponse}};
+
+/// Number of times a request is retried after the worker crashed
+const MAX_RESTARTS: usize = 1;
+/// Longest wait for an answer, loading the model and a large image on the CPU included
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// A long-lived OCR helper process
+///
+/// Requests are written as JSON lines on its stdin and answered on its stdout,
+/// so the OCR model is only loaded once. The process is started lazily and
+/// restarted when it dies. A process that does not answer within the timeout
+/// is killed, the next request starts a fresh one.
+pub struct OcrWorker {
+    program: String,
+    args: Vec<String>,
+    process: Mutex<Option<WorkerProcess>>,
+    /// Last known state, kept apart so it can be read while a request runs
+    status: Mutex<WorkerStatus>,
+    timeout: Duration,
+    next_id: AtomicU64,
+    restarts: AtomicU64,
+}
+
+struct WorkerProcess {
+    child: Child,
+    stdin: ChildStdin,
+    /// Lines of stdout, read by a thread so the wait for an answer can time out
+    lines: Receiver<io::Result<String>>,
+}
+
+/// State of the worker process, as seen from the viewer
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkerStatus {
+    /// No request was sent yet
+    NotStarted,
+    /// The worker answered a health request
+    Running(WorkerHealth),
+    /// The worker could not be reached
+    Unavailable(String),
+}
+
+impl fmt::Display for WorkerStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WorkerStatus::NotStarted => write!(f, "not started"),
+            WorkerStatus::Running(health) => write!(f, "{} ({})", health.status, health.engine),
+            WorkerStatus::Unavailable(e) => write!(f, "unavailable: {}", e),
+        }
+    }
+}
+
+impl OcrWorker {
+    pub fn new(program: impl Into<String>, args: Vec<String>) -> Self {
+        Self {
+            program: program.into(),
+            args,
+            process: Mutex::new(None),
+            status: Mutex::new(WorkerStatus::NotStarted),
+            timeout: RESPONSE_TIMEOUT,
+            next_id: AtomicU64::new(1),
+            restarts: AtomicU64::new(0),
+        }
+    }
+
+    /// Number of times the process had to be restarted
+    pub fn restarts(&self) -> u64 {
+        self.restarts.load(Ordering::Relaxed)
+    }
+
+    /// Last known state of the worker, without sending it anything
+    ///
+    /// The health is asked when the process starts, and the state becomes
+    /// unavailable when a request fails.
+    pub fn status(&self) -> WorkerStatus {
+        self.status.lock().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+    }
+
+    fn set_status(&self, status: WorkerStatus) {
+        *self.status.lock().unwrap_or_else(|poisoned| poisoned.into_inner()) = status;
+    }
+
+    /// Send a request and wait for its result, restarting the worker if needed
+    pub fn request(&self, method: WorkerMethod) -> Result<serde_json::Value, OcrError> {
+        let request = self.new_request(method);
+        let mut process = self.lock();
+        let mut attempt = 0;
+        loop {
+            let result = self.start(&mut process).and_then(|worker| worker.send(&request, self.timeout));
+            match result {
+                Ok(response) => return response.into_result(),
+                Err(e) => {
+                    // The worker died, hangs or is out of sync: kill it and start a fresh one
+                    if let Some(mut dead) = process.take() {
+                        let _ = dead.child.kill();
+                        let _ = dead.child.wait();
+                    }
+                    self.set_status(WorkerStatus::Unavailable(e.to_string()));
+                    if let OcrError::Timeout(_) = e {
+                        // The same request would likely hang again, the next one gets a fresh worker
+                        self.restarts.fetch_add(1, Ordering::Relaxed);
+                        return Err(e);
+                    }
+                    if attempt == MAX_RESTARTS {
+                        return Err(e);
+                    }
+                    attempt += 1;
+                    self.restarts.fetch_add(1, Ordering::Relaxed);
+                    eprintln!("OCR worker crashed ({}), restarting it", e);
+                }
+            }
+        }
+    }
+
+    fn new_request(&self, method: WorkerMethod) -> WorkerRequest {
+        WorkerRequest { id: self.next_id.fetch_add(1, Ordering::Relaxed), method }
+    }
+
+    /// The running process, spawned and asked for its health if there is none
+    fn start<'a>(&self, process: &'a mut Option<WorkerProcess>) -> Result<&'a mut WorkerProcess, OcrError> {
+        if process.is_none() {
+            let worker = process.insert(self.spawn()?);
+            let health = self.new_request(WorkerMethod::Health);
+            let result = worker.send(&health, self.timeout)?.into_result()?;
+            self.set_status(match serde_json::from_value(result) {
+                Ok(health) => WorkerStatus::Running(health),
+                Err(e) => WorkerStatus::Unavailable(e.to_string()),
+            });
+        }
+        Ok(process.as_mut().expect("The process was just started"))
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Option<WorkerProcess>> {
+        // A panic while holding the lock leaves at worst a dead process, which is restarted
+        self.process.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn spawn(&self) -> Result<WorkerProcess, OcrError> {
+        let spawn_error = |source| OcrError::Spawn { program: self.program.clone(), source };
+        let mut child = Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .map_err(spawn_error)?;
+        let stdin = child.stdin.take().expect("stdin is piped");
+        let mut stdout = BufReader::new(child.stdout.take().expect("stdout is piped"));
+        let (sender, lines) = mpsc::channel();
+        // Ends with the process, when its stdout is closed
+        std::thread::Builder::new()
+            .name("ocr-worker-output".to_string())
+            .spawn(move || loop {
+                let mut line = String::new();
+                match stdout.read_line(&mut line) {
+                    Ok(0) => return,
+                    Ok(_) => {
+                        if sender.send(Ok(line)).is_err() {
+                            return;
+                        }
+                    },
+                    Err(e) => {
+                        let _ = sender.send(Err(e));
+                        return;
+                    },
+                }
+            })
+            .map_err(spawn_error)?;
+        Ok(WorkerProcess { child, stdin, lines })
+    }
+}
+
+impl Drop for OcrWorker {
+    fn drop(&mut self) {
+        if let Some(mut process) = self.lock().take() {
+            // Closing stdin lets the worker exit on its own
+            drop(process.stdin);
+            let _ = process.child.wait();
+        }
+    }
+}
+
+impl WorkerProcess {
+    /// Write a request and read lines until its response comes back, for at most `timeout`
+    fn send(&mut self, request: &WorkerRequest, timeout: Duration) -> Result<WorkerResponse, OcrError> {
+        let broken = |e: std::io::Error| OcrError::Worker(e.to_string());
+        let mut line = serde_json::to_string(request).expect("worker requests are always serializable");
+        line.push('\n');
+        self.stdin.write_all(line.as_bytes()).map_err(broken)?;
+        self.stdin.flush().map_err(broken)?;
+
+        let deadline = Instant::now() + timeout;
+        loop {
+            let line = match self.lines.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(line) => line.map_err(broken)?,
+                Err(RecvTimeoutError::Timeout) => return Err(OcrError::Timeout(timeout)),
+                Err(RecvTimeoutError::Disconnected) => {
+                    let status = self.child.wait().map(|status| status.to_string()).unwrap_or_default();
+                    return Err(OcrError::Worker(format!("worker exited {}", status)));
+                },
+            };
+            match serde_json::from_str::<WorkerResponse>(&line) {
+                Ok(response) if response.id == Some(request.id) => return Ok(response),
+                // Answer to a request that was given up on
+                Ok(_) => continue,
+                Err(_) => eprintln!("OCR worker: {}", line.trim_end()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A worker answering every request with its health, except the OCR requests it hangs on until killed
+    #[cfg(unix)]
+    fn hanging_worker(timeout: Duration) -> OcrWorker {
+        let script = r#"
+            while read -r line; do
+                id=$(printf '%s' "$line" | sed 's/.*"id":\([0-9]*\).*/\1/')
+                case "$line" in *'"method":"ocr"'*) exec sleep 600 ;; esac
+                printf '{"id":%s,"result":{"status":"ready","engine":"sh"}}\n' "$id"
+            done
+        "#;
+        let mut worker = OcrWorker::new("sh", vec!["-c".to_string(), script.to_string()]);
+        worker.timeout = timeout;
+        worker
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn restarts_a_worker_that_does_not_answer() {
+        // Long enough for the shell to answer the health request on a busy machine
+        let worker = hanging_worker(Duration::from_secs(2));
+        assert_eq!(worker.status(), WorkerStatus::NotStarted);
+
+        let method = WorkerMethod::Ocr { path: "image.png".to_string(), languages: vec![], gpu: false };
+        assert!(matches!(worker.request(method), Err(OcrError::Timeout(timeout)) if timeout == Duration::from_secs(2)));
+        assert!(matches!(worker.status(), WorkerStatus::Unavailable(_)));
+        assert_eq!(worker.restarts(), 1);
+
+        // A fresh process answers the next request
+        assert!(worker.request(WorkerMethod::Health).is_ok());
+        assert!(matches!(worker.status(), WorkerStatus::Running(health) if health.engine == "sh"));
+    }
+}
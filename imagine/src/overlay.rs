@@ -0,0 +1,202 @@
+use druid::{
+    kurbo::Shape,
+    piet::{Text, TextLayout, TextLayoutBuilder},
+    theme, Affine, InternalLifeCycle, LifeCycle, LifeCycleCtx, Rect, WidgetPod,
+};
+use druid_selectable_label::SelectableLabel;
+
+use crate::prelude::*;
+
+use self::{image::{transform_mouse_event, ImageState}, ocr::Ocr};
+
+/// Part of the box height used by the glyphs
+const FONT_SIZE_RATIO: f64 = 0.8;
+/// Width of the box outlines, in screen pixels
+const OUTLINE_WIDTH: f64 = 1.5;
+/// Distance between the mouse and the tooltip
+const TOOLTIP_OFFSET: Vec2 = Vec2::new(12.0, 16.0);
+const TOOLTIP_PADDING: f64 = 4.0;
+
+/// A text widget placed over one OCR box
+struct OverlayItem {
+    /// Maps the box, laid out from `(0, 0)`, onto its polygon in image coordinates
+    transform: Affine,
+    /// Size of the box along its own axes
+    size: Size,
+    /// Width of the laid out text, stretched to the box width when painted
+    text_width: f64,
+    text: String,
+    confidence: f64,
+    widget: WidgetPod<String, Box<dyn Widget<String>>>,
+}
+
+impl OverlayItem {
+    /// Transform from the widget coordinates of the label to the zoomed image
+    fn zoomed_transform(&self, zoom: f64) -> Affine {
+        let stretch = if self.text_width > f64::EPSILON && self.size.width > f64::EPSILON {
+            self.size.width / self.text_width
+        } else {
+            1.0
+        };
+        Affine::scale(zoom) * self.transform * Affine::scale_non_uniform(stretch, 1.0)
+    }
+
+    /// Outline of the box in the zoomed image
+    fn outline(&self, zoom: f64) -> druid::kurbo::BezPath {
+        Affine::scale(zoom) * self.transform * self.size.to_rect().to_path(0.1)
+    }
+
+    /// Environment of the label: a font matching the box height
+    fn env(&self, env: &Env) -> Env {
+        let font = env.get(theme::UI_FONT).with_size((self.size.height * FONT_SIZE_RATIO).max(1.0));
+        env.clone().adding(theme::UI_FONT, font)
+    }
+}
+
+/// Invisible but selectable text laid over the recognized boxes of the image
+///
+/// Each label is laid out horizontally in the box's own axes, then drawn and
+/// hit-tested through an affine transform, so rotated or skewed text can be
+/// selected and copied where it appears on screen.
+///
+/// Boxes under the confidence threshold are left out, the others can be
+/// outlined with a color going from red to green with their confidence, and
+/// the hovered box shows its text and score in a tooltip.
+#[derive(Default)]
+pub struct OcrOverlay {
+    items: Vec<OverlayItem>,
+    /// Index of the hovered item and position of the mouse
+    hovered: Option<(usize, Point)>,
+}
+
+impl OcrOverlay {
+    fn rebuild(&mut self, ocr: Option<&Ocr>, min_confidence: f64) {
+        self.hovered = None;
+        self.items = ocr
+            .map(|ocr| ocr.content.iter().filter(|text_box| text_box.confidence >= min_confidence).map(|text_box| {
+                let (transform, size) = text_box.transform();
+                OverlayItem {
+                    transform,
+                    size,
+                    text_width: size.width,
+                    text: text_box.text.clone(),
+                    confidence: text_box.confidence,
+                    widget: WidgetPod::new(Box::new(
+                        SelectableLabel::new().with_text_color(Color::TRANSPARENT)
+                    ) as Box<dyn Widget<String>>),
+                }
+            }).collect())
+            .unwrap_or_default();
+    }
+
+    /// Item under the mouse, once the items have seen the event
+    fn update_hovered(&mut self, ctx: &mut EventCtx, event: &Event) {
+        let hovered = match event {
+            Event::MouseMove(mouse_event) => self
+                .items
+                .iter()
+                .position(|item| item.widget.is_hot())
+                .map(|i| (i, mouse_event.pos)),
+            Event::MouseDown(_) | Event::MouseUp(_) | Event::Wheel(_) => self.hovered,
+            _ => return,
+        };
+        if hovered != self.hovered {
+            self.hovered = hovered;
+            ctx.request_paint();
+        }
+    }
+
+    fn paint_tooltip(&self, ctx: &mut PaintCtx, env: &Env) {
+        let Some((item, mouse_pos)) = self.hovered.and_then(|(i, pos)| Some((self.items.get(i)?, pos))) else {
+            return;
+        };
+        let label = format!("{}\n{:.0}% confidence", item.text, item.confidence * 100.0);
+        let Ok(layout) = ctx
+            .text()
+            .new_text_layout(label)
+            .font(env.get(theme::UI_FONT).family, env.get(theme::TEXT_SIZE_NORMAL))
+            .text_color(colors::TOOLTIP_TEXT)
+            .build()
+        else {
+            return;
+        };
+        // Keep the tooltip inside the image when the mouse is near its right or bottom edge
+        let size = layout.size() + Size::new(TOOLTIP_PADDING, TOOLTIP_PADDING) * 2.0;
+        let bounds = ctx.size();
+        let origin = Point::new(
+            (mouse_pos.x + TOOLTIP_OFFSET.x).min(bounds.width - size.width).max(0.0),
+            (mouse_pos.y + TOOLTIP_OFFSET.y).min(bounds.height - size.height).max(0.0),
+        );
+        let rect = Rect::from_origin_size(origin, size);
+        ctx.fill(rect.to_rounded_rect(3.0), &colors::TOOLTIP_BACKGROUND);
+        ctx.draw_text(&layout, origin + Vec2::new(TOOLTIP_PADDING, TOOLTIP_PADDING));
+    }
+}
+
+impl Widget<ImageState> for OcrOverlay {
+    fn event(&mut self, ctx: &mut EventCtx, event: &Event, data: &mut ImageState, env: &Env) {
+        for item in &mut self.items {
+            let env = item.env(env);
+            let event = transform_mouse_event(event, item.zoomed_transform(data.zoom).inverse());
+            item.widget.event(ctx, &event, &mut item.text, &env);
+            // The invalidated region of a label ignores its transform
+            if item.widget.is_active() || item.widget.has_focus() {
+                ctx.request_paint();
+            }
+        }
+        self.update_hovered(ctx, event);
+    }
+
+    fn lifecycle(&mut self, ctx: &mut LifeCycleCtx, event: &LifeCycle, _data: &ImageState, env: &Env) {
+        // A label clears its selection when it loses the focus
+        if let LifeCycle::Internal(InternalLifeCycle::RouteFocusChanged { .. }) = event {
+            ctx.request_paint();
+        }
+        for item in &mut self.items {
+            let env = item.env(env);
+            item.widget.lifecycle(ctx, event, &item.text, &env);
+        }
+    }
+
+    fn update(&mut self, ctx: &mut UpdateCtx, old_data: &ImageState, data: &ImageState, env: &Env) {
+        if !old_data.ocr.same(&data.ocr) || old_data.min_confidence != data.min_confidence {
+            self.rebuild(data.ocr.as_ref(), data.min_confidence);
+            ctx.children_changed();
+            return;
+        }
+        if old_data.zoom != data.zoom || old_data.show_outlines != data.show_outlines {
+            ctx.request_paint();
+        }
+        for item in &mut self.items {
+            let env = item.env(env);
+            item.widget.update(ctx, &item.text, &env);
+        }
+    }
+
+    fn layout(&mut self, ctx: &mut LayoutCtx, bc: &BoxConstraints, _data: &ImageState, env: &Env) -> Size {
+        for item in &mut self.items {
+            let env = item.env(env);
+            let label_bc = BoxConstraints::new(Size::new(0.0, item.size.height), Size::new(f64::INFINITY, item.size.height));
+            item.text_width = item.widget.layout(ctx, &label_bc, &item.text, &env).width;
+            item.widget.set_origin(ctx, Point::ORIGIN);
+        }
+        bc.max()
+    }
+
+    fn paint(&mut self, ctx: &mut PaintCtx, data: &ImageState, env: &Env) {
+        if data.show_outlines {
+            for item in &self.items {
+                ctx.stroke(item.outline(data.zoom), &colors::confidence_color(item.confidence), OUTLINE_WIDTH);
+            }
+        }
+        for item in &mut self.items {
+            let env = item.env(env);
+            let transform = item.zoomed_transform(data.zoom);
+            ctx.with_save(|ctx| {
+                ctx.transform(transform);
+                item.widget.paint(ctx, &item.text, &env);
+            });
+        }
+        self.paint_tooltip(ctx, env);
+    }
+}
@@ -0,0 +1,376 @@
+//! Images with several pages: multi-page TIFF and the sizes of an ICO
+//!
+//! The image crate only decodes the first image of these files. The pages are
+//! found by walking the directories of the TIFF or the entries of the ICO, and
+//! each page is decoded by handing the image crate a file whose first image is
+//! that page: the offset of the first TIFF directory is patched, and the ICO
+//! entry is copied to an icon of its own.
+
+use std::{fs, ops::Range, path::Path};
+
+use ::image::{io::Reader, DynamicImage, ImageFormat, ImageResult};
+use druid::im::Vector;
+
+use crate::prelude::*;
+
+use self::{image::ImageState, loader::{LoadError, MAX_PIXELS}, metadata::{apply_orientation, Metadata}, navigation::NavigationStep, ocr::Ocr};
+
+/// Longest chain of TIFF directories followed, against loops in broken files
+const MAX_PAGES: usize = 10_000;
+
+/// The pages of a document and the text recognized in them
+#[derive(Clone, Data, Lens)]
+pub struct Document {
+    pub pages: Arc<Vec<Arc<ImageBuf>>>,
+    /// Index of the shown page
+    pub current: usize,
+    /// Text recognized in each page, the one of the shown page is also `ImageState::ocr`
+    pub ocr: Vector<Option<Ocr>>,
+}
+
+impl Document {
+    pub fn new(pages: Vec<Arc<ImageBuf>>) -> Self {
+        let ocr = pages.iter().map(|_| None).collect();
+        Self { pages: Arc::new(pages), current: 0, ocr }
+    }
+
+    /// Page `step` leads to, `None` at the ends and for the steps between images
+    pub fn target(&self, step: NavigationStep) -> Option<usize> {
+        match step {
+            NavigationStep::PreviousPage => self.current.checked_sub(1),
+            NavigationStep::NextPage => Some(self.current + 1).filter(|page| *page < self.pages.len()),
+            _ => None,
+        }
+    }
+
+    /// `2/5`
+    pub fn position(&self) -> String {
+        format!("{}/{}", self.current + 1, self.pages.len())
+    }
+
+    /// Whether the text of every page was recognized
+    pub fn is_recognized(&self) -> bool {
+        self.ocr.iter().all(Option::is_some)
+    }
+
+    /// The new pages of a reloaded document, still on the same page
+    pub fn continue_from(mut self, previous: &Document) -> Self {
+        self.current = previous.current.min(self.pages.len() - 1);
+        self
+    }
+}
+
+impl ImageState {
+    /// Index of the shown page, 0 for an image without pages
+    pub fn current_page(&self) -> usize {
+        self.document.as_ref().map_or(0, |document| document.current)
+    }
+
+    /// Show page `page` of the document with its text, returns whether its size changed
+    pub fn show_page(&mut self, page: usize) -> bool {
+        let Some(document) = &mut self.document else {
+            return false;
+        };
+        if page >= document.pages.len() {
+            return false;
+        }
+        // A region OCR may have changed the text of the page
+        document.ocr[document.current] = self.ocr.take();
+        document.current = page;
+        let image_buf = document.pages[page].clone();
+        let resized = image_buf.size() != self.image_buf.size();
+        self.image_buf = image_buf;
+        self.ocr = document.ocr[page].clone();
+        self.search.refresh(self.ocr.as_ref());
+        resized
+    }
+
+    /// Text recognized in every page, or in the image, in order
+    pub fn recognized_pages(&self) -> Vec<Ocr> {
+        match &self.document {
+            Some(document) => {
+                // The text of the current page may have been changed by a region OCR
+                let mut pages = document.ocr.clone();
+                pages[document.current] = self.ocr.clone();
+                pages.into_iter().flatten().collect()
+            },
+            None => self.ocr.iter().cloned().collect(),
+        }
+    }
+
+    /// Whether the text of the image, or of a page of the document, was recognized
+    pub fn has_text(&self) -> bool {
+        self.ocr.is_some() || self.document.as_ref().is_some_and(|document| document.ocr.iter().any(Option::is_some))
+    }
+
+    /// Keep the text of page `ocr.page`, and show it if it is the current page
+    pub fn set_ocr(&mut self, ocr: Ocr) {
+        if let Some(document) = &mut self.document {
+            if let Some(page_ocr) = document.ocr.get_mut(ocr.page) {
+                *page_ocr = Some(ocr.clone());
+            }
+            if ocr.page != document.current {
+                return;
+            }
+        }
+        self.search.refresh(Some(&ocr));
+        self.ocr = Some(ocr);
+    }
+}
+
+/// Where the pages are in a TIFF or ICO file
+enum Layout {
+    Tiff {
+        little_endian: bool,
+        /// BigTIFF, with 64 bits offsets
+        big: bool,
+        /// Offsets of the image directories
+        directories: Vec<u64>,
+    },
+    Ico {
+        /// 1 for icons, 2 for cursors
+        kind: u16,
+        /// Directory entry and data of each image, the largest first
+        entries: Vec<([u8; 16], Range<usize>)>,
+    },
+}
+
+/// A TIFF or ICO file and its pages
+struct PagedImage {
+    data: Vec<u8>,
+    layout: Layout,
+}
+
+impl PagedImage {
+    /// Find the pages of `data`, `None` if it is not a readable TIFF or ICO file
+    fn parse(data: Vec<u8>, format: ImageFormat) -> Option<Self> {
+        let layout = match format {
+            ImageFormat::Tiff => tiff_layout(&data)?,
+            ImageFormat::Ico => ico_layout(&data)?,
+            _ => return None,
+        };
+        Some(Self { data, layout })
+    }
+
+    fn len(&self) -> usize {
+        match &self.layout {
+            Layout::Tiff { directories, .. } => directories.len(),
+            Layout::Ico { entries, .. } => entries.len(),
+        }
+    }
+
+    /// Decode the page at `index`
+    fn decode(&mut self, index: usize) -> ImageResult<DynamicImage> {
+        match &self.layout {
+            Layout::Tiff { little_endian, big, directories } => {
+                // Point the header to the directory of the page, and restore it after
+                let (field, offset) = if *big { (8..16, directories[index]) } else { (4..8, directories[index]) };
+                let original = self.data[field.clone()].to_vec();
+                let bytes = match (*big, *little_endian) {
+                    (true, true) => offset.to_le_bytes().to_vec(),
+                    (true, false) => offset.to_be_bytes().to_vec(),
+                    (false, true) => (offset as u32).to_le_bytes().to_vec(),
+                    (false, false) => (offset as u32).to_be_bytes().to_vec(),
+                };
+                self.data[field.clone()].copy_from_slice(&bytes);
+                let image = ::image::load_from_memory_with_format(&self.data, ImageFormat::Tiff);
+                self.data[field].copy_from_slice(&original);
+                image
+            },
+            Layout::Ico { kind, entries } => {
+                let (entry, range) = &entries[index];
+                let mut icon = Vec::with_capacity(22 + range.len());
+                icon.extend_from_slice(&[0, 0]);
+                icon.extend_from_slice(&kind.to_le_bytes());
+                icon.extend_from_slice(&1u16.to_le_bytes());
+                icon.extend_from_slice(&entry[..12]);
+                icon.extend_from_slice(&22u32.to_le_bytes());
+                icon.extend_from_slice(&self.data[range.clone()]);
+                ::image::load_from_memory_with_format(&icon, ImageFormat::Ico)
+            },
+        }
+    }
+}
+
+/// Every page of the image at `path`, `None` if its format has no pages or it has a single one
+///
+/// Every page is turned upright according to `orientation`, the EXIF orientation of the file.
+pub fn decode_pages(path: &Path, format: ImageFormat, orientation: u32) -> Result<Option<Vec<Arc<ImageBuf>>>, LoadError> {
+    if !matches!(format, ImageFormat::Tiff | ImageFormat::Ico) {
+        return Ok(None);
+    }
+    let Some(mut paged) = PagedImage::parse(fs::read(path)?, format).filter(|paged| paged.len() > 1) else {
+        return Ok(None);
+    };
+    let mut pages = Vec::with_capacity(paged.len());
+    let mut pixels = 0;
+    for index in 0..paged.len() {
+        let page = apply_orientation(paged.decode(index)?, orientation).to_rgba8();
+        let (width, height) = page.dimensions();
+        // All the pages are kept in memory
+        pixels += width as u64 * height as u64;
+        if pixels > MAX_PIXELS {
+            return Err(LoadError::TooLarge { width, height });
+        }
+        let image_buf = ImageBuf::from_raw(page.into_raw(), druid::piet::ImageFormat::RgbaSeparate, width as usize, height as usize);
+        pages.push(Arc::new(image_buf));
+    }
+    Ok(Some(pages))
+}
+
+/// The TIFF or ICO file at `path` and its pages, `None` for the other formats
+///
+/// Like the loader, the format is guessed from the content, so the viewer and
+/// the OCR agree on the pages of a misnamed file.
+fn open_paged(path: &Path) -> Option<PagedImage> {
+    let format = Reader::open(path).ok()?.with_guessed_format().ok()?.format()?;
+    if !matches!(format, ImageFormat::Tiff | ImageFormat::Ico) {
+        return None;
+    }
+    PagedImage::parse(fs::read(path).ok()?, format)
+}
+
+/// Number of pages of the image at `path`, 1 for the images without pages
+pub fn page_count(path: &Path) -> usize {
+    open_paged(path).map_or(1, |paged| paged.len().max(1))
+}
+
+/// Decode page `page` of the image at `path`, the first page is the image itself for other formats
+///
+/// Like in the viewer, photos are turned upright.
+pub fn open_page(path: &Path, page: usize) -> ImageResult<DynamicImage> {
+    let image = match open_paged(path) {
+        Some(mut paged) if page < paged.len() => paged.decode(page)?,
+        _ => Reader::open(path)?.with_guessed_format()?.decode()?,
+    };
+    Ok(apply_orientation(image, Metadata::read(path).orientation))
+}
+
+fn tiff_layout(data: &[u8]) -> Option<Layout> {
+    let little_endian = match data.get(..2)? {
+        b"II" => true,
+        b"MM" => false,
+        _ => return None,
+    };
+    let read = |offset: u64, len: usize| -> Option<u64> {
+        let bytes = data.get(usize::try_from(offset).ok()?..)?.get(..len)?;
+        let mut value = 0;
+        for i in 0..len {
+            let byte = if little_endian { bytes[len - 1 - i] } else { bytes[i] };
+            value = value << 8 | byte as u64;
+        }
+        Some(value)
+    };
+    // Sizes of the offsets, of the entry count and of an entry
+    let (big, mut offset) = match read(2, 2)? {
+        42 => (false, read(4, 4)?),
+        43 => (true, read(8, 8)?),
+        _ => return None,
+    };
+    let (offset_len, count_len, entry_len) = if big { (8, 8, 20) } else { (4, 2, 12) };
+
+    let mut directories = Vec::new();
+    while offset != 0 && directories.len() < MAX_PAGES && !directories.contains(&offset) {
+        directories.push(offset);
+        // The count comes from the file, a huge one must not overflow
+        let count = read(offset, count_len)?;
+        let next = count.checked_mul(entry_len)?.checked_add(offset)?.checked_add(count_len as u64)?;
+        offset = read(next, offset_len)?;
+    }
+    (!directories.is_empty()).then_some(Layout::Tiff { little_endian, big, directories })
+}
+
+fn ico_layout(data: &[u8]) -> Option<Layout> {
+    let read_u16 = |offset: usize| Some(u16::from_le_bytes(data.get(offset..offset + 2)?.try_into().ok()?));
+    let read_u32 = |offset: usize| Some(u32::from_le_bytes(data.get(offset..offset + 4)?.try_into().ok()?) as usize);
+    let kind = read_u16(2)?;
+    if read_u16(0)? != 0 || !matches!(kind, 1 | 2) {
+        return None;
+    }
+    let mut entries = (0..read_u16(4)? as usize)
+        .map(|i| {
+            let entry: [u8; 16] = data.get(6 + 16 * i..6 + 16 * (i + 1))?.try_into().ok()?;
+            let (size, offset) = (read_u32(6 + 16 * i + 8)?, read_u32(6 + 16 * i + 12)?);
+            data.get(offset..offset.checked_add(size)?)?;
+            Some((entry, offset..offset + size))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    // A size of 0 stands for 256 pixels, the largest and deepest images first
+    let side = |byte: u8| if byte == 0 { 256 } else { byte as u32 };
+    entries.sort_by_key(|(entry, _)| std::cmp::Reverse((side(entry[0]) * side(entry[1]), u16::from_le_bytes([entry[6], entry[7]]))));
+    (!entries.is_empty()).then_some(Layout::Ico { kind, entries })
+}
+
+#[cfg(test)]
+mod tests {
+    use ::image::{codecs::ico::{IcoEncoder, IcoFrame}, ColorType, GenericImageView};
+    use tiff::encoder::{colortype, TiffEncoder};
+
+    use super::*;
+
+    #[test]
+    fn finds_the_pages_of_a_tiff() {
+        let mut data = std::io::Cursor::new(Vec::new());
+        let mut encoder = TiffEncoder::new(&mut data).unwrap();
+        encoder.write_image::<colortype::Gray8>(4, 2, &[0; 8]).unwrap();
+        encoder.write_image::<colortype::RGB8>(3, 5, &[255; 45]).unwrap();
+        let mut paged = PagedImage::parse(data.into_inner(), ImageFormat::Tiff).unwrap();
+
+        assert_eq!(paged.len(), 2);
+        assert_eq!(paged.decode(1).unwrap().dimensions(), (3, 5));
+        assert_eq!(paged.decode(0).unwrap().dimensions(), (4, 2));
+    }
+
+    #[test]
+    fn guesses_the_format_of_a_misnamed_tiff() {
+        let path = std::env::temp_dir().join(format!("imagine-pages-{}.png", std::process::id()));
+        let mut encoder = TiffEncoder::new(fs::File::create(&path).unwrap()).unwrap();
+        encoder.write_image::<colortype::Gray8>(4, 2, &[0; 8]).unwrap();
+        encoder.write_image::<colortype::Gray8>(3, 5, &[0; 15]).unwrap();
+        drop(encoder);
+
+        assert_eq!(page_count(&path), 2);
+        assert_eq!(open_page(&path, 1).unwrap().dimensions(), (3, 5));
+        // Turned a quarter, like a photo
+        let pages = decode_pages(&path, ImageFormat::Tiff, 6).unwrap().unwrap();
+        assert_eq!(pages.iter().map(|page| (page.width(), page.height())).collect::<Vec<_>>(), [(2, 4), (5, 3)]);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn refuses_a_tiff_with_an_oversized_directory() {
+        // BigTIFF header, then a directory at offset 16 claiming u64::MAX entries
+        let mut data = b"II".to_vec();
+        data.extend_from_slice(&43u16.to_le_bytes());
+        data.extend_from_slice(&8u16.to_le_bytes());
+        data.extend_from_slice(&0u16.to_le_bytes());
+        data.extend_from_slice(&16u64.to_le_bytes());
+        data.extend_from_slice(&u64::MAX.to_le_bytes());
+        data.extend_from_slice(&[0; 32]);
+        assert!(PagedImage::parse(data, ImageFormat::Tiff).is_none());
+    }
+
+    #[test]
+    fn finds_the_sizes_of_an_icon() {
+        let image = |side: u32| {
+            let mut png = Vec::new();
+            ::image::RgbaImage::from_pixel(side, side, ::image::Rgba([128; 4]))
+                .write_to(&mut std::io::Cursor::new(&mut png), ImageFormat::Png)
+                .unwrap();
+            png
+        };
+        let (small, large) = (image(16), image(32));
+        let frames = [
+            IcoFrame::with_encoded(small.as_slice(), 16, 16, ColorType::Rgba8).unwrap(),
+            IcoFrame::with_encoded(large.as_slice(), 32, 32, ColorType::Rgba8).unwrap(),
+        ];
+        let mut data = Vec::new();
+        IcoEncoder::new(&mut data).encode_images(&frames).unwrap();
+        let mut paged = PagedImage::parse(data, ImageFormat::Ico).unwrap();
+
+        assert_eq!(paged.len(), 2);
+        assert_eq!(paged.decode(0).unwrap().dimensions(), (32, 32));
+        assert_eq!(paged.decode(1).unwrap().dimensions(), (16, 16));
+        assert!(PagedImage::parse(b"not an icon".to_vec(), ImageFormat::Ico).is_none());
+    }
+}
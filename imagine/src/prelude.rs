@@ -1,6 +1,6 @@
 pub use std::sync::Arc;
 
-pub use crate::{ui, image, AppState, delegate, dialog, background, colors, ocr};
+pub use crate::{ui, image, AppState, delegate, dialog, background, colors, ocr, overlay, cli, config, search, export, batch, startup, navigation, watcher, loader, animation, pages, metadata};
 
 pub use druid::widget::prelude::*;
 pub use druid::{
@@ -0,0 +1,299 @@
+use druid::{
+    im::Vector,
+    keyboard_types::Key,
+    kurbo::BezPath,
+    widget::{Button, Controller, Label, TextBox},
+    LensExt,
+};
+
+use crate::prelude::*;
+
+use self::{
+    delegate::{CLOSE_FIND, FIND_STEP, FOCUS_FIND},
+    image::ImageState,
+    ocr::Ocr,
+};
+
+/// Height, in screen pixels, a hit is zoomed to when it is smaller
+const READABLE_HEIGHT: f64 = 32.0;
+/// Part of the viewport width a hit can take when it is zoomed
+const MAX_VIEWPORT_WIDTH: f64 = 0.8;
+/// Query characters per typo accepted by the fuzzy matching
+const CHARS_PER_TYPO: usize = 4;
+
+/// State of the find bar
+#[derive(Clone, Data, Lens, Default)]
+pub struct SearchState {
+    pub open: bool,
+    pub query: String,
+    /// Indices of the matching boxes in `Ocr::content`, in reading order
+    pub matches: Vector<usize>,
+    /// Index of the selected hit in `matches`
+    pub current: usize,
+    /// The query was edited since the last step, the selected hit was not shown yet
+    pub edited: bool,
+}
+
+impl SearchState {
+    /// Look for the query in new OCR results
+    pub fn refresh(&mut self, ocr: Option<&Ocr>) {
+        self.matches = match ocr {
+            Some(ocr) if self.open => find_matches(ocr, &self.query).into(),
+            _ => Vector::new(),
+        };
+        if self.current >= self.matches.len() {
+            self.current = 0;
+        }
+    }
+
+    /// Select the hit `delta` positions away, wrapping around
+    ///
+    /// The first step after an edit selects the first hit, or the last one going back.
+    pub fn step(&mut self, delta: isize) {
+        if self.matches.is_empty() {
+            return;
+        }
+        if std::mem::take(&mut self.edited) {
+            self.current = if delta < 0 { self.matches.len() - 1 } else { 0 };
+            return;
+        }
+        let len = self.matches.len() as isize;
+        self.current = (self.current as isize + delta).rem_euclid(len) as usize;
+    }
+
+    /// Index in `Ocr::content` of the selected hit
+    pub fn current_match(&self) -> Option<usize> {
+        self.matches.get(self.current).copied()
+    }
+
+    fn summary(&self) -> String {
+        match self.matches.len() {
+            _ if self.query.trim().is_empty() => String::new(),
+            0 => "No match".to_string(),
+            len => format!("{} of {}", self.current + 1, len),
+        }
+    }
+}
+
+/// Boxes whose text contains `query`, ignoring case and a few typos
+pub fn find_matches(ocr: &Ocr, query: &str) -> Vec<usize> {
+    let query = query.trim().to_lowercase().chars().collect::<Vec<_>>();
+    if query.is_empty() {
+        return Vec::new();
+    }
+    let max_typos = query.len() / CHARS_PER_TYPO;
+    let mut matches = ocr
+        .content
+        .iter()
+        .enumerate()
+        .filter(|(_, text_box)| {
+            let text = text_box.text.to_lowercase().chars().collect::<Vec<_>>();
+            substring_distance(&query, &text) <= max_typos
+        })
+        .map(|(i, _)| i)
+        .collect::<Vec<_>>();
+    // Top to bottom, then left to right
+    matches.sort_by(|a, b| {
+        let (a, b) = (ocr.content[*a].bounding_rect(), ocr.content[*b].bounding_rect());
+        a.y0.total_cmp(&b.y0).then(a.x0.total_cmp(&b.x0))
+    });
+    matches
+}
+
+/// Smallest edit distance between `pattern` and any substring of `text`
+fn substring_distance(pattern: &[char], text: &[char]) -> usize {
+    // Distances between the prefixes of the pattern and the substrings ending at the current character
+    let mut column = (0..=pattern.len()).collect::<Vec<_>>();
+    let mut best = pattern.len();
+    for c in text {
+        let mut diagonal = column[0];
+        for (i, p) in pattern.iter().enumerate() {
+            let substitution = diagonal + usize::from(p != c);
+            diagonal = column[i + 1];
+            column[i + 1] = substitution.min(column[i + 1] + 1).min(column[i] + 1);
+        }
+        best = best.min(column[pattern.len()]);
+    }
+    best
+}
+
+impl ImageState {
+    /// Zoom on the selected hit and center it in a viewport of `viewport` size
+    pub fn show_match(&mut self, viewport: Size) {
+        let hit = self.search.current_match().and_then(|i| self.ocr.as_ref()?.content.get(i));
+        let Some(rect) = hit.map(|text_box| text_box.bounding_rect()) else {
+            return;
+        };
+        if rect.height() > 0.0 && rect.height() * self.zoom < READABLE_HEIGHT {
+            self.zoom = READABLE_HEIGHT / rect.height();
+        }
+        if rect.width() > 0.0 && rect.width() * self.zoom > viewport.width * MAX_VIEWPORT_WIDTH {
+            self.zoom = (viewport.width * MAX_VIEWPORT_WIDTH / rect.width()).max(self.min_zoom);
+        }
+        self.center = rect.center().to_vec2() * self.zoom;
+        // Zooming out stays possible down to a fifth of the fitting zoom, as after a change of image
+        let image_size = self.image_buf.size();
+        if image_size.area() > 0.0 && viewport.area() > 0.0 {
+            let fit = (viewport.width / image_size.width).min(viewport.height / image_size.height);
+            self.min_zoom = self.zoom.min(fit) / 5.0;
+        }
+    }
+
+    /// Outlines of the hits in the zoomed image, the selected one first
+    pub fn match_outlines(&self) -> Vec<BezPath> {
+        let Some(ocr) = &self.ocr else {
+            return Vec::new();
+        };
+        let current = self.search.current_match();
+        current
+            .into_iter()
+            .chain(self.search.matches.iter().copied().filter(|i| Some(*i) != current))
+            .filter_map(|i| ocr.content.get(i))
+            .map(|text_box| {
+                let mut outline = BezPath::new();
+                for (i, point) in text_box.boxes.iter().enumerate() {
+                    let point = (point.to_vec2() * self.zoom).to_point();
+                    if i == 0 { outline.move_to(point) } else { outline.line_to(point) }
+                }
+                outline.close_path();
+                outline
+            })
+            .collect()
+    }
+}
+
+/// Text field of the find bar: Enter and Shift+Enter step through the hits, Escape closes
+struct FindController;
+
+impl<W: Widget<ImageState>> Controller<ImageState, W> for FindController {
+    fn event(&mut self, child: &mut W, ctx: &mut EventCtx, event: &Event, data: &mut ImageState, env: &Env) {
+        match event {
+            Event::Command(cmd) if cmd.is(FOCUS_FIND) => {
+                ctx.request_focus();
+                ctx.set_handled();
+            },
+            Event::KeyDown(key) if key.key == Key::Enter => {
+                ctx.submit_command(FIND_STEP.with(if key.mods.shift() { -1 } else { 1 }));
+                ctx.set_handled();
+            },
+            Event::KeyDown(key) if key.key == Key::Escape => {
+                ctx.submit_command(CLOSE_FIND);
+                ctx.set_handled();
+            },
+            _ => {
+                let query = data.search.query.clone();
+                child.event(ctx, event, data, env);
+                // Only the highlights follow the typing, Enter shows the hits
+                if data.search.query != query {
+                    data.search.current = 0;
+                    data.search.edited = true;
+                    data.search.refresh(data.ocr.as_ref());
+                }
+            },
+        }
+    }
+}
+
+/// Bar shown above the image by Ctrl+F
+pub fn find_bar() -> impl Widget<ImageState> {
+    let query = TextBox::new()
+        .with_placeholder("Find in text")
+        .lens(ImageState::search.then(SearchState::query))
+        .controller(FindController);
+    Flex::row()
+        .with_flex_child(query.expand_width(), 1.0)
+        .with_spacer(8.0)
+        .with_child(Label::dynamic(|data: &ImageState, _| data.search.summary()))
+        .with_spacer(8.0)
+        .with_child(Button::new("Previous").on_click(|ctx, _, _| ctx.submit_command(FIND_STEP.with(-1))))
+        .with_child(Button::new("Next").on_click(|ctx, _, _| ctx.submit_command(FIND_STEP.with(1))))
+        .with_child(Button::new("Close").on_click(|ctx, _, _| ctx.submit_command(CLOSE_FIND)))
+        .padding(4.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ocr::OcrTextBox;
+
+    fn ocr(texts: &[&str]) -> Ocr {
+        let content = texts.iter().enumerate().map(|(i, text)| {
+            let (top, bottom) = (i * 10, i * 10 + 8);
+            let line = format!("([0, {top}]-[50, {top}]-[50, {bottom}]-[0, {bottom}]);{text};1");
+            OcrTextBox::parse_ocr_text_box(&line).unwrap()
+        }).collect::<Vec<_>>();
+        Ocr::new("image.png", content)
+    }
+
+    #[test]
+    fn matches_ignoring_case() {
+        let ocr = ocr(&["Tracy Profiler", "Statistics", "profiler"]);
+        assert_eq!(find_matches(&ocr, "PROFILER"), vec![0, 2]);
+        assert!(find_matches(&ocr, "  ").is_empty());
+    }
+
+    #[test]
+    fn tolerates_typos_in_long_queries() {
+        let ocr = ocr(&["1= Statistics", "Messages"]);
+        assert_eq!(find_matches(&ocr, "statistcs"), vec![0]);
+        assert_eq!(find_matches(&ocr, "mess"), vec![1]);
+        // One typo per four characters of the query
+        assert_eq!(find_matches(&ocr, "mxss"), vec![1]);
+        assert_eq!(find_matches(&ocr, "messagxx"), vec![1]);
+        assert!(find_matches(&ocr, "mxsx").is_empty());
+        assert!(find_matches(&ocr, "mxssagxx").is_empty());
+        // Short queries must match exactly
+        assert!(find_matches(&ocr, "mas").is_empty());
+    }
+
+    #[test]
+    fn measures_substring_distance() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(substring_distance(&chars("find"), &chars("Find zone")), 1);
+        assert_eq!(substring_distance(&chars("zone"), &chars("Find zone")), 0);
+        assert_eq!(substring_distance(&chars("abc"), &chars("")), 3);
+    }
+
+    #[test]
+    fn zooms_on_the_selected_hit() {
+        let mut state = ImageState {
+            image_buf: Arc::new(ImageBuf::from_raw(vec![0; 100 * 100], druid::piet::ImageFormat::Grayscale, 100, 100)),
+            ocr: Some(Ocr::new("image.png", vec![OcrTextBox::parse_ocr_text_box("([10, 10]-[30, 10]-[30, 14]-[10, 14]);small;1").unwrap()])),
+            ..ImageState::default()
+        };
+        state.search.open = true;
+        state.search.query = "small".to_string();
+        state.search.refresh(state.ocr.as_ref());
+        state.show_match(Size::new(400.0, 400.0));
+        assert_eq!(state.zoom, READABLE_HEIGHT / 4.0);
+        assert_eq!(state.center, Vec2::new(20.0, 12.0) * state.zoom);
+        // The image fits the viewport at a zoom of 4
+        assert_eq!(state.min_zoom, 0.8);
+    }
+
+    #[test]
+    fn steps_around_the_hits() {
+        let mut search = SearchState { open: true, query: "a".to_string(), ..Default::default() };
+        search.refresh(Some(&ocr(&["a", "b", "a"])));
+        assert_eq!(search.current_match(), Some(0));
+        search.step(-1);
+        assert_eq!(search.current_match(), Some(2));
+        search.step(1);
+        assert_eq!(search.summary(), "1 of 2");
+    }
+
+    #[test]
+    fn shows_the_first_hit_after_an_edit() {
+        let ocr = ocr(&["a", "b", "a", "a"]);
+        let mut search = SearchState { open: true, query: "a".to_string(), edited: true, ..Default::default() };
+        search.refresh(Some(&ocr));
+        search.step(1);
+        assert_eq!(search.current_match(), Some(0));
+        search.step(1);
+        assert_eq!(search.current_match(), Some(2));
+
+        search.edited = true;
+        search.step(-1);
+        assert_eq!(search.current_match(), Some(3));
+    }
+}
@@ -0,0 +1,85 @@
+//! Startup of the main window
+//!
+//! The window is hidden when it connects, and stays hidden until the first
+//! image is decoded: it is then sized to the aspect ratio of the image and
+//! shown. When no image is given on the command line, the open dialog is
+//! shown first and cancelling it quits instead of leaving an invisible
+//! process behind.
+
+use druid::Screen;
+
+use crate::prelude::*;
+
+/// Part of the screen work area the first window can take
+const MAX_SCREEN_PART: f64 = 0.8;
+/// Length of the longest side of the window for small images
+const MIN_WINDOW_SIDE: f64 = 480.0;
+/// Window size when the screen size is unknown
+const DEFAULT_WORK_AREA: Size = Size::new(1500.0, 1000.0);
+
+/// Where the main window is in its startup
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Startup {
+    /// The window is not connected yet
+    #[default]
+    Connecting,
+    /// Hidden behind the open dialog
+    Choosing,
+    /// Hidden until the first image is decoded
+    Opening,
+    /// Shown with an image, the startup is over
+    Ready,
+}
+
+impl Startup {
+    /// Whether the window is still waiting for its first image
+    pub fn is_pending(&self) -> bool {
+        *self != Startup::Ready
+    }
+}
+
+/// Work area of the primary monitor
+pub fn work_area() -> Size {
+    Screen::get_monitors()
+        .into_iter()
+        .find(|monitor| monitor.is_primary())
+        .map(|monitor| monitor.virtual_work_rect().size())
+        .filter(|size| size.width > 0.0 && size.height > 0.0)
+        .unwrap_or(DEFAULT_WORK_AREA)
+}
+
+/// Size of a window showing an image of `image_size`, with the same aspect ratio
+///
+/// Large images are shrunk to fit in the work area and small ones enlarged so
+/// the window is still easy to use.
+pub fn window_size_for(image_size: Size, work_area: Size) -> Size {
+    if image_size.width <= 0.0 || image_size.height <= 0.0 {
+        return work_area * MAX_SCREEN_PART;
+    }
+    let max = work_area * MAX_SCREEN_PART;
+    let mut scale = (max.width / image_size.width).min(max.height / image_size.height).min(1.0);
+    let longest_side = image_size.width.max(image_size.height) * scale;
+    if longest_side < MIN_WINDOW_SIDE {
+        scale *= MIN_WINDOW_SIDE / longest_side;
+    }
+    (image_size * scale).round()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCREEN: Size = Size::new(1920.0, 1080.0);
+
+    #[test]
+    fn keeps_the_aspect_ratio() {
+        assert_eq!(window_size_for(Size::new(4000.0, 3000.0), SCREEN), Size::new(1152.0, 864.0));
+        assert_eq!(window_size_for(Size::new(800.0, 600.0), SCREEN), Size::new(800.0, 600.0));
+        assert_eq!(window_size_for(Size::new(120.0, 60.0), SCREEN), Size::new(480.0, 240.0));
+    }
+
+    #[test]
+    fn falls_back_to_the_screen_for_empty_images() {
+        assert_eq!(window_size_for(Size::ZERO, SCREEN), SCREEN * 0.8);
+    }
+}
@@ -1,18 +1,208 @@
-use druid::{WidgetExt, Menu, MenuItem};
+use druid::{WidgetExt, FileInfo, LensExt, Menu, MenuItem, SysMods, UnitPoint, widget::{Button, Checkbox, CrossAxisAlignment, Either, Label, LineBreaking, List, SizedBox, Slider, Spinner, ZStack}};
 
-use crate::{prelude::*, AppState, dialog::open_image_dialog};
+use crate::{prelude::*, AppState, animation::AnimationController, dialog::{export_dialog, frame_dialog, open_image_dialog}, delegate::{COPY_ALL_TEXT, FORCE_OCR, NAVIGATE, OCR_ALL_PAGES, OPEN_FIND, RUN_OCR, SHOW_PAGE}, navigation::{NavigationController, NavigationStep, SortOrder}, ocr::options::{OcrDevice, LANGUAGES}};
 
-use self::{image::ImageView, background::CustomBackgroundWidget};
+use self::{image::{ImageState, ImageStatus, ImageView}, background::CustomBackgroundWidget, metadata::Metadata};
 
 pub fn build_ui() -> impl Widget<AppState> {
+    let find_bar = Either::new(|data: &ImageState, _| data.search.open, search::find_bar(), SizedBox::empty());
     CustomBackgroundWidget::new(
-    ImageView::new(
-            image::ImageWidget::default()
-        ).expand()
-        .lens(AppState::image_state)
+        Flex::column()
+            .cross_axis_alignment(CrossAxisAlignment::Fill)
+            .with_child(find_bar)
+            .with_flex_child(
+                Flex::row()
+                    .cross_axis_alignment(CrossAxisAlignment::Fill)
+                    .with_flex_child(
+                        Either::new(
+                            |data: &ImageState, _| matches!(data.status, ImageStatus::Error(_)),
+                            error_panel(),
+                            ZStack::new(
+                                ImageView::new(
+                                    image::ImageWidget::default()
+                                ).expand(),
+                            )
+                            .with_aligned_child(loading_spinner(), UnitPoint::TOP_RIGHT)
+                            .with_aligned_child(task_error_banner(), UnitPoint::BOTTOM)
+                            .controller(AnimationController::default()),
+                        ),
+                        1.0,
+                    )
+                    .with_child(Either::new(|data: &ImageState, _| data.show_info, info_panel(), SizedBox::empty())),
+                1.0,
+            )
+            .with_child(Either::new(|data: &ImageState, _| data.animation.is_some(), animation_bar(), SizedBox::empty()))
+            .with_child(Either::new(|data: &ImageState, _| data.document.is_some(), page_bar(), SizedBox::empty()))
+            .with_child(Either::new(|data: &ImageState, _| data.ocr.is_some(), ocr_bar(), SizedBox::empty()))
+            .lens(AppState::image_state)
+    ).controller(NavigationController)
+}
+
+/// Spins in a corner of the image view while the next image is decoded
+fn loading_spinner() -> impl Widget<ImageState> {
+    Either::new(
+        |data: &ImageState, _| matches!(data.status, ImageStatus::Loading(_)),
+        Spinner::new().fix_size(24.0, 24.0).padding(12.0),
+        SizedBox::empty(),
     )
 }
 
+/// Why the last OCR job, export or saved frame failed, over the bottom of the image until dismissed
+fn task_error_banner() -> impl Widget<ImageState> {
+    let banner = Flex::row()
+        .with_flex_child(
+            Label::dynamic(|data: &ImageState, _| data.task_error.clone().unwrap_or_default())
+                .with_text_color(colors::TOOLTIP_TEXT)
+                .with_line_break_mode(LineBreaking::WordWrap),
+            1.0,
+        )
+        .with_spacer(8.0)
+        .with_child(Button::new("Dismiss").on_click(|_, data: &mut ImageState, _| data.task_error = None))
+        .padding(8.0)
+        .background(colors::TOOLTIP_BACKGROUND);
+    Either::new(|data: &ImageState, _| data.task_error.is_some(), banner, SizedBox::empty())
+}
+
+/// Shown instead of the image when it could not be opened
+fn error_panel() -> impl Widget<ImageState> {
+    let title = Label::dynamic(|data: &ImageState, _| {
+        let name = std::path::Path::new(&data.path).file_name().unwrap_or_default().to_string_lossy().into_owned();
+        format!("Cannot open {}", name)
+    });
+    let reason = Label::dynamic(|data: &ImageState, _| match &data.status {
+        ImageStatus::Error(error) => format!("Reason: {}", error),
+        _ => String::new(),
+    });
+    let actions = Flex::row()
+        .with_child(Button::new("Retry").on_click(|ctx, data: &mut ImageState, _| {
+            ctx.submit_command(commands::OPEN_FILE.with(FileInfo { path: data.path.clone().into(), format: None }))
+        }))
+        .with_spacer(8.0)
+        .with_child(Button::new("Open another image…").on_click(|ctx, _, _| {
+            ctx.submit_command(commands::SHOW_OPEN_PANEL.with(open_image_dialog()))
+        }));
+    Flex::column()
+        .with_child(title.with_text_size(18.0))
+        .with_spacer(8.0)
+        .with_child(reason.with_line_break_mode(LineBreaking::WordWrap))
+        .with_spacer(16.0)
+        .with_child(actions)
+        .padding(24.0)
+        .center()
+}
+
+/// Window title: the name of the image and its position in the folder
+pub fn window_title(data: &AppState, _: &Env) -> String {
+    // The position is already the one of the image being loaded
+    let path = match &data.image_state.status {
+        ImageStatus::Loading(path) => path,
+        _ => &data.image_state.path,
+    };
+    let name = std::path::Path::new(path).file_name().map(|name| name.to_string_lossy().into_owned());
+    match (name, data.navigation.position()) {
+        (Some(name), Some(position)) => format!("{} ({}) - Imagine", name, position),
+        (Some(name), None) => format!("{} - Imagine", name),
+        (None, _) => "Imagine".to_string(),
+    }
+}
+
+/// Dimensions and EXIF metadata of the image, next to it
+fn info_panel() -> impl Widget<ImageState> {
+    let entry = || {
+        Flex::column()
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .with_child(Label::dynamic(|(name, _): &(String, String), _| name.clone()).with_text_size(12.0))
+            .with_child(Label::dynamic(|(_, value): &(String, String), _| value.clone()).with_line_break_mode(LineBreaking::WordWrap))
+            .padding((0.0, 4.0))
+    };
+    let dimensions = |data: &ImageState| {
+        let size = data.image_buf.size();
+        format!("{} × {} pixels", size.width, size.height)
+    };
+    Scroll::new(
+        Flex::column()
+            .cross_axis_alignment(CrossAxisAlignment::Start)
+            .with_child(Label::new("Dimensions").with_text_size(12.0))
+            .with_child(Label::dynamic(move |data: &ImageState, _| dimensions(data)))
+            .with_spacer(4.0)
+            .with_child(List::new(entry).lens(ImageState::metadata.then(Metadata::entries)))
+            .with_child(Either::new(
+                |data: &ImageState, _| data.metadata.entries.is_empty(),
+                Label::new("No EXIF metadata").with_text_size(12.0),
+                SizedBox::empty(),
+            ))
+            .padding(8.0),
+    )
+    .vertical()
+    .fix_width(240.0)
+}
+
+/// Playback controls of an animated image, under the image
+fn animation_bar() -> impl Widget<ImageState> {
+    let playing = |data: &ImageState| data.animation.as_ref().is_some_and(|animation| animation.playing);
+    Flex::row()
+        .with_child(Button::new("Previous frame").on_click(|_, data: &mut ImageState, _| data.pause_and_step(-1)))
+        .with_child(Button::dynamic(move |data: &ImageState, _| if playing(data) { "Pause" } else { "Play" }.to_string())
+            .on_click(|_, data: &mut ImageState, _| data.toggle_playback()))
+        .with_child(Button::new("Next frame").on_click(|_, data: &mut ImageState, _| data.pause_and_step(1)))
+        .with_spacer(8.0)
+        .with_child(Label::dynamic(|data: &ImageState, _| match &data.animation {
+            Some(animation) => format!("Frame {}/{}", animation.current + 1, animation.frames.len()),
+            None => String::new(),
+        }))
+        .with_spacer(16.0)
+        .with_child(Button::new("Slower").on_click(|_, data: &mut ImageState, _| data.change_speed(false)))
+        .with_spacer(4.0)
+        .with_child(Label::dynamic(|data: &ImageState, _| {
+            data.animation.as_ref().map(|animation| format!("{}×", animation.speed)).unwrap_or_default()
+        }))
+        .with_spacer(4.0)
+        .with_child(Button::new("Faster").on_click(|_, data: &mut ImageState, _| data.change_speed(true)))
+        .padding(4.0)
+}
+
+/// Pages of a multi-page image, under the image
+fn page_bar() -> impl Widget<ImageState> {
+    let target = |data: &ImageState, step| data.document.as_ref().and_then(|document| document.target(step));
+    let step = move |label: &'static str, step: NavigationStep| {
+        Button::new(label)
+            .on_click(move |ctx, data: &mut ImageState, _| {
+                if let Some(page) = target(data, step) {
+                    ctx.submit_command(SHOW_PAGE.with(page));
+                }
+            })
+            .disabled_if(move |data: &ImageState, _| target(data, step).is_none())
+    };
+    Flex::row()
+        .with_child(step("Previous page", NavigationStep::PreviousPage))
+        .with_spacer(8.0)
+        .with_child(Label::dynamic(|data: &ImageState, _| match &data.document {
+            Some(document) => format!("Page {}", document.position()),
+            None => String::new(),
+        }))
+        .with_spacer(8.0)
+        .with_child(step("Next page", NavigationStep::NextPage))
+        .with_spacer(16.0)
+        .with_child(Button::new("Recognize all pages")
+            .on_click(|ctx, _, _| ctx.submit_command(OCR_ALL_PAGES))
+            .disabled_if(|data: &ImageState, _| data.document.as_ref().is_none_or(|document| document.is_recognized()))
+        )
+        .padding(4.0)
+}
+
+/// Display settings of the recognized boxes, under the image
+fn ocr_bar() -> impl Widget<ImageState> {
+    Flex::row()
+        .with_child(Label::new("Minimum confidence"))
+        .with_spacer(8.0)
+        .with_child(Slider::new().with_range(0.0, 1.0).with_step(0.05).lens(ImageState::min_confidence))
+        .with_spacer(8.0)
+        .with_child(Label::dynamic(|data: &ImageState, _| format!("{:.0}%", data.min_confidence * 100.0)))
+        .with_spacer(16.0)
+        .with_child(Checkbox::new("Outlines").lens(ImageState::show_outlines))
+        .padding(4.0)
+}
+
 #[allow(unused_assignments)]
 pub fn make_menu(_: Option<WindowId>, _state: &AppState, _: &Env) -> Menu<AppState> {
     let mut base: Menu<AppState> = Menu::empty();
@@ -23,7 +213,142 @@ pub fn make_menu(_: Option<WindowId>, _state: &AppState, _: &Env) -> Menu<AppSta
                 .entry(MenuItem::new(LocalizedString::new("common-menu-file-open"))
                     .command(commands::SHOW_OPEN_PANEL.with(open_image_dialog()))
                 )
-        );
+                .entry(MenuItem::new(LocalizedString::new("imagine-menu-export").with_placeholder("Export text…"))
+                    .on_activate(|ctx, data: &mut AppState, _| {
+                        ctx.submit_command(commands::SHOW_SAVE_PANEL.with(export_dialog(&data.image_state.path)))
+                    })
+                    .hotkey(SysMods::Cmd, "e")
+                    .enabled_if(|data: &AppState, _| data.image_state.has_text())
+                )
+        )
+        .entry(
+            Menu::new(LocalizedString::new("common-menu-edit-menu"))
+                .entry(MenuItem::new(LocalizedString::new("imagine-menu-copy-all-text").with_placeholder("Copy all text"))
+                    .command(COPY_ALL_TEXT)
+                    .hotkey(SysMods::CmdShift, "c")
+                    .enabled_if(|data: &AppState, _| data.image_state.has_text())
+                )
+                .entry(MenuItem::new(LocalizedString::new("imagine-menu-find").with_placeholder("Find"))
+                    .command(OPEN_FIND)
+                    .hotkey(SysMods::Cmd, "f")
+                )
+        )
+        .entry(view_menu())
+        .entry(ocr_menu());
 
     base
 }
+
+/// Navigation through the images of the folder and the pages of the image, and the info panel
+fn view_menu() -> Menu<AppState> {
+    let sort = SortOrder::ALL.iter().fold(
+        Menu::new(LocalizedString::new("imagine-menu-sort").with_placeholder("Sort by")),
+        |menu, order| {
+            let order = *order;
+            menu.entry(MenuItem::new(order.label())
+                .selected_if(move |data: &AppState, _| data.navigation.sort == order)
+                .on_activate(move |_, data: &mut AppState, _| data.navigation.set_sort(order))
+            )
+        },
+    );
+    let step = |id: &'static str, label: &'static str, step: NavigationStep| {
+        MenuItem::new(LocalizedString::new(id).with_placeholder(label))
+            .command(NAVIGATE.with(step))
+            .enabled_if(move |data: &AppState, _| data.navigation.target(step).is_some())
+    };
+    let page = |id: &'static str, label: &'static str, step: NavigationStep| {
+        MenuItem::new(LocalizedString::new(id).with_placeholder(label))
+            .command(NAVIGATE.with(step))
+            .enabled_if(move |data: &AppState, _| data.image_state.document.as_ref().and_then(|document| document.target(step)).is_some())
+    };
+
+    Menu::new(LocalizedString::new("imagine-menu-view").with_placeholder("View"))
+        .entry(MenuItem::new(LocalizedString::new("imagine-menu-show-info").with_placeholder("Image info"))
+            .selected_if(|data: &AppState, _| data.image_state.show_info)
+            .on_activate(|_, data: &mut AppState, _| data.image_state.show_info = !data.image_state.show_info)
+            .hotkey(SysMods::Cmd, "i")
+        )
+        .separator()
+        .entry(step("imagine-menu-previous-image", "Previous image", NavigationStep::Previous))
+        .entry(step("imagine-menu-next-image", "Next image", NavigationStep::Next))
+        .entry(step("imagine-menu-first-image", "First image", NavigationStep::First))
+        .entry(step("imagine-menu-last-image", "Last image", NavigationStep::Last))
+        .separator()
+        .entry(page("imagine-menu-previous-page", "Previous page", NavigationStep::PreviousPage))
+        .entry(page("imagine-menu-next-page", "Next page", NavigationStep::NextPage))
+        .separator()
+        .entry(sort)
+        .entry(animation_menu())
+}
+
+/// Playback of the animated images, the keys are handled by the image view
+fn animation_menu() -> Menu<AppState> {
+    let animated = |data: &AppState, _: &Env| data.image_state.animation.is_some();
+    let item = |id: &'static str, label: &'static str, action: fn(&mut ImageState)| {
+        MenuItem::new(LocalizedString::new(id).with_placeholder(label))
+            .on_activate(move |_, data: &mut AppState, _| action(&mut data.image_state))
+            .enabled_if(animated)
+    };
+
+    Menu::new(LocalizedString::new("imagine-menu-animation").with_placeholder("Animation"))
+        .entry(item("imagine-menu-play", "Play", ImageState::toggle_playback)
+            .selected_if(|data: &AppState, _| data.image_state.animation.as_ref().is_some_and(|animation| animation.playing))
+        )
+        .entry(item("imagine-menu-previous-frame", "Previous frame", |data| data.pause_and_step(-1)))
+        .entry(item("imagine-menu-next-frame", "Next frame", |data| data.pause_and_step(1)))
+        .separator()
+        .entry(item("imagine-menu-slower", "Slower", |data| data.change_speed(false)))
+        .entry(item("imagine-menu-faster", "Faster", |data| data.change_speed(true)))
+        .separator()
+        .entry(MenuItem::new(LocalizedString::new("imagine-menu-export-frame").with_placeholder("Export current frame…"))
+            .on_activate(|ctx, data: &mut AppState, _| {
+                let frame = data.image_state.animation.as_ref().map_or(0, |animation| animation.current);
+                ctx.submit_command(commands::SHOW_SAVE_PANEL.with(frame_dialog(&data.image_state.path, frame)))
+            })
+            .enabled_if(animated)
+        )
+}
+
+/// Engine options, changing one runs the OCR of the current image again
+fn ocr_menu() -> Menu<AppState> {
+    let languages = LANGUAGES.iter().fold(
+        Menu::new(LocalizedString::new("imagine-menu-ocr-languages").with_placeholder("Languages")),
+        |menu, language| {
+            let code = language.code;
+            menu.entry(MenuItem::new(language.name)
+                .selected_if(move |data: &AppState, _| data.ocr_options.languages.contains(&code.to_string()))
+                .on_activate(move |ctx, data: &mut AppState, _| {
+                    data.ocr_options.toggle_language(code);
+                    ctx.submit_command(RUN_OCR);
+                })
+            )
+        },
+    );
+
+    Menu::new(LocalizedString::new("imagine-menu-ocr").with_placeholder("OCR"))
+        .entry(MenuItem::new(LocalizedString::new("imagine-menu-force-ocr").with_placeholder("Run OCR again"))
+            .command(FORCE_OCR)
+            .hotkey(SysMods::Cmd, "r")
+            .enabled_if(|data: &AppState, _| !data.image_state.path.is_empty())
+        )
+        .entry(MenuItem::new(LocalizedString::new("imagine-menu-ocr-all-pages").with_placeholder("Recognize all pages"))
+            .command(OCR_ALL_PAGES)
+            .enabled_if(|data: &AppState, _| data.image_state.document.is_some())
+        )
+        .entry(MenuItem::new(LocalizedString::new("imagine-menu-auto-ocr").with_placeholder("Recognize text when opening"))
+            .selected_if(|data: &AppState, _| data.auto_ocr)
+            .on_activate(|_, data: &mut AppState, _| data.auto_ocr = !data.auto_ocr)
+        )
+        .separator()
+        .entry(languages)
+        .entry(MenuItem::new(LocalizedString::new("imagine-menu-ocr-gpu").with_placeholder("Use the GPU"))
+            .selected_if(|data: &AppState, _| data.ocr_options.device == OcrDevice::Gpu)
+            .on_activate(|ctx, data: &mut AppState, _| {
+                data.ocr_options.device = match data.ocr_options.device {
+                    OcrDevice::Cpu => OcrDevice::Gpu,
+                    OcrDevice::Gpu => OcrDevice::Cpu,
+                };
+                ctx.submit_command(RUN_OCR);
+            })
+        )
+}
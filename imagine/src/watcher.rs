@@ -0,0 +1,147 @@
+//! Watch the folder of the current image for changes
+//!
+//! Screenshot tools and render jobs overwrite the image in several writes, or
+//! write a temporary file and rename it, so the events are gathered until the
+//! folder has been quiet for a moment and then sent to the UI at once with
+//! [`FILES_CHANGED`].
+
+use std::{
+    fs, mem,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, RecvTimeoutError},
+    time::Duration,
+};
+
+use druid::{ExtEventSink, Target};
+use notify::{event::ModifyKind, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::delegate::FILES_CHANGED;
+
+/// Quiet time after the last event before the changes are sent
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Changes in the watched folder, sent to the UI with [`FILES_CHANGED`]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FileChanges {
+    /// Files that were written, created or renamed
+    pub modified: Vec<PathBuf>,
+    /// Whether files were created, removed or renamed
+    pub listing_changed: bool,
+}
+
+impl FileChanges {
+    fn is_empty(&self) -> bool {
+        self.modified.is_empty() && !self.listing_changed
+    }
+
+    fn add(&mut self, event: &Event) {
+        let listing_changed = matches!(event.kind, EventKind::Create(_) | EventKind::Remove(_) | EventKind::Modify(ModifyKind::Name(_)));
+        let modified = match event.kind {
+            EventKind::Create(_) | EventKind::Modify(_) | EventKind::Any => true,
+            EventKind::Access(_) | EventKind::Remove(_) | EventKind::Other => false,
+        };
+        self.listing_changed |= listing_changed;
+        if modified {
+            for path in &event.paths {
+                if !self.modified.contains(path) {
+                    self.modified.push(path.clone());
+                }
+            }
+        }
+    }
+
+    /// Whether the file at `path` was modified
+    pub fn contains(&self, path: &Path) -> bool {
+        // The events carry the paths of the watched folder, which is canonical
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        self.modified.contains(&path)
+    }
+}
+
+/// Watches the folder of the current image
+#[derive(Default)]
+pub struct FileWatcher {
+    /// Dropping the watcher stops its thread
+    watcher: Option<RecommendedWatcher>,
+    directory: Option<PathBuf>,
+}
+
+impl FileWatcher {
+    /// Watch the folder of the image at `path`, instead of the previous one
+    pub fn watch(&mut self, path: &Path, handle: ExtEventSink) {
+        let directory = path.parent().filter(|parent| !parent.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let Ok(directory) = fs::canonicalize(directory) else {
+            return;
+        };
+        if self.directory.as_ref() == Some(&directory) {
+            return;
+        }
+        self.watcher = None;
+        self.directory = None;
+
+        let (sender, receiver) = mpsc::channel();
+        let mut watcher = match notify::recommended_watcher(sender) {
+            Ok(watcher) => watcher,
+            Err(e) => {
+                eprintln!("Failed to start the file watcher: {}", e);
+                return;
+            },
+        };
+        if let Err(e) = watcher.watch(&directory, RecursiveMode::NonRecursive) {
+            eprintln!("Failed to watch {}: {}", directory.display(), e);
+            return;
+        }
+        std::thread::Builder::new()
+            .name("file-watcher".to_string())
+            .spawn(move || debounce(receiver, handle))
+            .expect("Failed to spawn the file watcher thread");
+        self.watcher = Some(watcher);
+        self.directory = Some(directory);
+    }
+}
+
+/// Gather the events until the folder is quiet, then send them
+fn debounce(receiver: Receiver<notify::Result<Event>>, handle: ExtEventSink) {
+    let mut changes = FileChanges::default();
+    loop {
+        let event = if changes.is_empty() {
+            receiver.recv().map_err(|_| RecvTimeoutError::Disconnected)
+        } else {
+            receiver.recv_timeout(DEBOUNCE)
+        };
+        match event {
+            Ok(Ok(event)) => changes.add(&event),
+            Ok(Err(e)) => eprintln!("File watcher error: {}", e),
+            Err(RecvTimeoutError::Timeout) => {
+                if handle.submit_command(FILES_CHANGED, mem::take(&mut changes), Target::Auto).is_err() {
+                    return;
+                }
+            },
+            // The watcher was dropped
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use notify::event::{AccessKind, CreateKind, DataChange, RemoveKind, RenameMode};
+
+    use super::*;
+
+    #[test]
+    fn sorts_out_the_events() {
+        let mut changes = FileChanges::default();
+        changes.add(&Event::new(EventKind::Access(AccessKind::Any)).add_path("/shots/a.png".into()));
+        assert!(changes.is_empty());
+        changes.add(&Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content))).add_path("/shots/a.png".into()));
+        changes.add(&Event::new(EventKind::Modify(ModifyKind::Data(DataChange::Content))).add_path("/shots/a.png".into()));
+        assert_eq!(changes, FileChanges { modified: vec!["/shots/a.png".into()], listing_changed: false });
+
+        changes.add(&Event::new(EventKind::Remove(RemoveKind::File)).add_path("/shots/b.png".into()));
+        assert!(changes.listing_changed && !changes.contains(Path::new("/shots/b.png")));
+        changes.add(&Event::new(EventKind::Create(CreateKind::File)).add_path("/shots/c.png".into()));
+        changes.add(&Event::new(EventKind::Modify(ModifyKind::Name(RenameMode::To))).add_path("/shots/d.png".into()));
+        assert_eq!(changes.modified.len(), 3);
+    }
+}